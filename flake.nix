@@ -22,6 +22,18 @@
           src = ./.;
           cargoLock.lockFile = ./Cargo.lock;
 
+          nativeBuildInputs = [ pkgs.installShellFiles ];
+
+          postInstall = ''
+            installShellCompletion --cmd nichts \
+              --bash <($out/bin/nichts --generate-completions bash) \
+              --zsh <($out/bin/nichts --generate-completions zsh) \
+              --fish <($out/bin/nichts --generate-completions fish)
+
+            $out/bin/nichts --generate-man man
+            installManPage man/*.1
+          '';
+
           meta = {
             description = "nichts is a nix profile wrapper with pacman-like syntax";
             homepage = "https://github.com/k3tzchen/nichts";
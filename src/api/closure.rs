@@ -0,0 +1,93 @@
+use fast_strip_ansi::strip_ansi_string;
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{api::{history::{HistoryVersions, UNSET_VERSION}, store::StorePaths}, backend::NixBackend, error::Error};
+
+/// Bytes per unit as printed by `nix store diff-closures`.
+static SIZE_UNITS: &[(&str, f64)] = &[("B", 1.0), ("KiB", 1024.0), ("MiB", 1048576.0), ("GiB", 1073741824.0), ("TiB", 1099511627776.0)];
+
+#[derive(Debug, Serialize)]
+pub struct ClosureChange {
+  pub name: String,
+  #[serde(rename = "oldVersions")]
+  pub old_versions: Vec<String>,
+  #[serde(rename = "newVersions")]
+  pub new_versions: Vec<String>,
+  /// Only reported by nix for changes of at least 8 KiB.
+  #[serde(rename = "sizeDelta")]
+  pub size_delta: Option<i64>,
+}
+
+/// `∅` stands for a package that is not in the closure, `ε` for one without a version.
+fn parse_versions(list: &str) -> Vec<String> {
+  if list.trim() == UNSET_VERSION {
+    return Vec::new();
+  }
+
+  list.split(", ").map(|version| version.trim().to_string()).collect()
+}
+
+fn parse_size(size: &str) -> Option<i64> {
+  let (number, unit) = size.split_once(' ')?;
+  let factor = SIZE_UNITS.iter().find(|(name, _)| *name == unit).map(|(_, factor)| factor)?;
+
+  number.parse::<f64>().ok().map(|number| (number * factor).round() as i64)
+}
+
+#[derive(Debug, Serialize)]
+pub struct ClosureDiff {
+  pub from: usize,
+  pub to: usize,
+  pub changes: Vec<ClosureChange>,
+  #[serde(rename = "totalSizeDelta")]
+  pub total_size_delta: i64,
+}
+
+impl ClosureDiff {
+  /// Runs `nix store diff-closures` on the store paths of both generations, or on their generation
+  /// links when the history came from `nix profile history`. The total compares their whole closures,
+  /// since nix leaves out the size of changes below 8 KiB.
+  pub fn new(nix: &dyn NixBackend, versions: &HistoryVersions, from: usize, to: usize) -> Result<Self, Error> {
+    let profile_link = nix.profile_link();
+    let store_path = |generation: usize| {
+      let version = versions.get(generation)
+        .ok_or(Error::Unknown { code: 1, message: format!("generation {generation} does not exist") })?;
+
+      version.store_path.clone()
+        .or_else(|| profile_link.as_ref().map(|link| link.directory.join(format!("{name}-{generation}-link", name = link.name)).display().to_string()))
+        .ok_or(Error::Unknown { code: 1, message: format!("cannot locate the store path of generation {generation}") })
+    };
+
+    let (before, after) = (store_path(from)?, store_path(to)?);
+    let mut diff = ClosureDiff::parse(from, to, &nix.diff_closures(&before, &after)?);
+
+    // queried one at a time, as a generation link is reported under the store path it points to
+    let closure_size = |path: String| StorePaths::new(nix, &[path], true).map(|paths| paths.total_size() as i64);
+    diff.total_size_delta = closure_size(after)? - closure_size(before)?;
+
+    Ok(diff)
+  }
+
+  /// Parses lines like `firefox: 91.0 → 92.0, +2345.6 KiB`, where either part may be missing.
+  pub fn parse(from: usize, to: usize, output: &str) -> Self {
+    let line_pattern = Regex::new(r"^(.+?): (?:(.*?) → (.*?))?(?:(?:, )?([+-]\d+(?:\.\d+)? [KMGT]?i?B))?$").unwrap();
+
+    let changes: Vec<ClosureChange> = output.lines().filter_map(|line| {
+      let line = strip_ansi_string(line.trim()).to_string();
+      let captures = line_pattern.captures(&line)?;
+
+      let change = ClosureChange {
+        name: captures[1].to_string(),
+        old_versions: captures.get(2).map(|versions_list| parse_versions(versions_list.as_str())).unwrap_or_default(),
+        new_versions: captures.get(3).map(|versions_list| parse_versions(versions_list.as_str())).unwrap_or_default(),
+        size_delta: captures.get(4).and_then(|size| parse_size(size.as_str())),
+      };
+
+      (captures.get(2).is_some() || change.size_delta.is_some()).then_some(change)
+    }).collect();
+
+    let total_size_delta = changes.iter().filter_map(|change| change.size_delta).sum();
+    ClosureDiff { from, to, changes, total_size_delta }
+  }
+}
@@ -0,0 +1,178 @@
+use std::{env, fs::{canonicalize, read_dir, read_link, read_to_string}, path::{Path, PathBuf}, time::UNIX_EPOCH};
+
+use regex::Regex;
+use serde::Serialize;
+
+use crate::{api::query::{Package, PackageListing}, backend::{GenerationLink, ProfileLink}};
+
+/// Upper bound on symlink hops, matching the usual `ELOOP` limit.
+static MAX_SYMLINK_HOPS: usize = 40;
+
+/// Store path entries that `buildEnv` does not link into the profile.
+static HIDDEN_ENTRIES: &[&str] = &["nix-support"];
+
+fn walk(root: &Path, directory: &Path, files: &mut Vec<PathBuf>) {
+  let Ok(entries) = read_dir(directory) else {
+    return;
+  };
+
+  for entry in entries.flatten() {
+    let path = entry.path();
+    let Ok(relative) = path.strip_prefix(root) else {
+      continue;
+    };
+
+    if HIDDEN_ENTRIES.iter().any(|hidden| relative.starts_with(hidden)) {
+      continue;
+    }
+
+    match entry.file_type() {
+      Ok(file_type) if file_type.is_dir() => walk(root, &path, files),
+      _ => files.push(relative.to_path_buf()),
+    }
+  }
+}
+
+pub fn list_files(store_path: &Path, bin_only: bool) -> Vec<PathBuf> {
+  let mut files = Vec::new();
+
+  if bin_only {
+    if let Ok(entries) = read_dir(store_path.join("bin")) {
+      files.extend(entries.flatten().map(|entry| PathBuf::from("bin").join(entry.file_name())));
+    }
+  } else {
+    walk(store_path, store_path, &mut files);
+  }
+
+  files.sort();
+  files
+}
+
+pub fn profile_dir(profile: Option<&str>) -> Option<PathBuf> {
+  if let Some(profile) = profile {
+    return Some(PathBuf::from(profile));
+  }
+
+  env::var_os("HOME").map(|home| PathBuf::from(home).join(".nix-profile"))
+}
+
+fn generation_link() -> Regex {
+  Regex::new(r"^(.+)-(\d+)-link$").unwrap()
+}
+
+pub fn profile_link(profile: Option<&str>) -> Option<ProfileLink> {
+  let generation_link = generation_link();
+  let mut current = profile_dir(profile)?;
+
+  (0..MAX_SYMLINK_HOPS).find_map(|_| {
+    let target = read_link(&current).ok()?;
+    current = match current.parent() {
+      Some(parent) if target.is_relative() => parent.join(target),
+      _ => target,
+    };
+
+    let file_name = current.file_name()?.to_string_lossy().to_string();
+    let captures = generation_link.captures(&file_name)?;
+    Some(ProfileLink { directory: current.parent()?.to_path_buf(), name: captures[1].to_string(), active: captures[2].parse::<usize>().ok()? })
+  })
+}
+
+pub fn profile_generations(profile: Option<&str>) -> Option<Vec<GenerationLink>> {
+  let generation_link = generation_link();
+  let ProfileLink { directory, name, active } = profile_link(profile)?;
+
+  let mut generations: Vec<GenerationLink> = read_dir(&directory).ok()?.flatten().filter_map(|entry| {
+    let file_name = entry.file_name().to_string_lossy().to_string();
+    let captures = generation_link.captures(&file_name).filter(|captures| captures[1] == name)?;
+    let number = captures[2].parse::<usize>().ok()?;
+
+    let path = entry.path();
+    let created = path.symlink_metadata().and_then(|metadata| metadata.modified()).ok()
+      .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+      .map(|duration| duration.as_secs())
+      .unwrap_or(0);
+
+    Some(GenerationLink {
+      number,
+      store_path: read_link(&path).ok()?.to_string_lossy().to_string(),
+      created,
+      active: number == active,
+      manifest: read_to_string(path.join("manifest.json")).ok(),
+    })
+  }).collect();
+
+  generations.sort_by_key(|generation| generation.number);
+  Some(generations)
+}
+
+pub fn find_target(profile: Option<&str>, target: &str) -> Option<PathBuf> {
+  if target.contains('/') {
+    let path = PathBuf::from(target);
+    return path.symlink_metadata().is_ok().then(|| std::path::absolute(&path).unwrap_or(path));
+  }
+
+  let profile_bin = profile_dir(profile).map(|profile| profile.join("bin"));
+  let search_path = env::var_os("PATH").map(|path| env::split_paths(&path).collect::<Vec<PathBuf>>()).unwrap_or_default();
+
+  profile_bin.into_iter().chain(search_path)
+    .map(|directory| directory.join(target))
+    .find(|path| path.symlink_metadata().is_ok())
+}
+
+#[derive(Debug, Serialize)]
+pub struct Owner {
+  pub path: String,
+  pub resolved: String,
+  pub name: String,
+  pub version: String,
+  #[serde(rename = "attrPath")]
+  pub attr_path: String,
+  #[serde(rename = "originalUrl")]
+  pub original_url: String,
+}
+
+impl Owner {
+  pub fn new(listing: &PackageListing, path: &Path) -> Option<Self> {
+    let (resolved, name, package) = resolve_owner(listing, path)?;
+
+    Some(Owner {
+      path: path.display().to_string(),
+      resolved: resolved.display().to_string(),
+      name: name.clone(),
+      version: package.version.clone(),
+      attr_path: package.attr_path.clone(),
+      original_url: package.original_url.clone(),
+    })
+  }
+}
+
+fn resolve_owner<'a>(listing: &'a PackageListing, path: &Path) -> Option<(PathBuf, &'a String, &'a Package)> {
+  let mut current = path.to_path_buf();
+
+  for _ in 0..MAX_SYMLINK_HOPS {
+    if let Some((name, package)) = listing.owner(&current) {
+      return Some((current, name, package));
+    }
+
+    let parent = current.parent().and_then(|parent| canonicalize(parent).ok());
+    if let (Some(parent), Some(file_name)) = (parent, current.file_name()) {
+      current = parent.join(file_name);
+
+      if let Some((name, package)) = listing.owner(&current) {
+        return Some((current, name, package));
+      }
+    }
+
+    let Ok(target) = read_link(&current) else {
+      break;
+    };
+
+    current = match current.parent() {
+      Some(parent) if target.is_relative() => parent.join(target),
+      _ => target,
+    };
+  }
+
+  let resolved = canonicalize(path).ok()?;
+  listing.owner(&resolved).map(|(name, package)| (resolved, name, package))
+}
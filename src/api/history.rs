@@ -1,7 +1,7 @@
-use std::{collections::{BTreeMap, btree_map::Iter}, fmt::Display};
+use std::{collections::{BTreeMap, BTreeSet, btree_map::Iter}, fmt::Display};
 use serde::{Deserialize, Serialize};
 
-use crate::{Cli, command::catch_output, error::Error, operations::Operations};
+use crate::{api::{log::format_timestamp, query::PackageListing}, backend::{GenerationLink, NixBackend}, error::Error};
 use fast_strip_ansi::strip_ansi_string;
 
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
@@ -36,6 +36,7 @@ impl Clone for HistoryActions {
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct HistoryPackage {
+  pub name: String,
   pub action: HistoryActions,
   pub previous_version: Option<String>,
   pub current_version: Option<String>,
@@ -51,12 +52,37 @@ impl HistoryPackage {
   fn flake(&self) -> String {
     format!("{url}#{attribute}", url = self.flake_url, attribute = self.flake_attribute)
   }
+
+  /// The elements added, removed or changed in version between two generations.
+  pub fn diff(before: &PackageListing, after: &PackageListing) -> Vec<Self> {
+    let names: BTreeSet<&String> = before.keys().chain(after.keys()).collect();
+
+    names.into_iter().filter_map(|name| {
+      let (previous, current) = (before.get(name), after.get(name));
+      let action = match (previous, current) {
+        (None, Some(_)) => HistoryActions::Added,
+        (Some(_), None) => HistoryActions::Removed,
+        (Some(previous), Some(current)) if previous.version != current.version => HistoryActions::Upgraded,
+        _ => return None,
+      };
+
+      let package = current.or(previous)?;
+      Some(HistoryPackage {
+        name: name.clone(),
+        action,
+        previous_version: previous.map(|package| package.version.clone()),
+        current_version: current.map(|package| package.version.clone()),
+        flake_url: package.original_url.clone(),
+        flake_attribute: package.attr_path.clone(),
+      })
+    }).collect()
+  }
 }
 
 impl From<String> for HistoryPackage {
   fn from(value: String) -> Self {
     if value.rfind("->").is_none() {
-      return HistoryPackage { action: HistoryActions::None, previous_version: None, current_version: None, flake_url: String::new(), flake_attribute: String::new() };
+      return HistoryPackage { name: String::new(), action: HistoryActions::None, previous_version: None, current_version: None, flake_url: String::new(), flake_attribute: String::new() };
     }
 
     let mut parts = value.split(": ");
@@ -98,7 +124,7 @@ impl From<String> for HistoryPackage {
       if current.eq(UNSET_VERSION) {
         action = HistoryActions::Removed;
       } else {
-        if !previous_version.is_none() {
+        if previous_version.is_some() {
           action = HistoryActions::Upgraded;
         }
 
@@ -115,19 +141,23 @@ impl From<String> for HistoryPackage {
       }
     }
 
-    HistoryPackage { action, previous_version, current_version, flake_url, flake_attribute }
+    // newer nix versions print element names instead of flake references
+    let name = if flake_attribute.is_empty() { flake_url.clone() } else { flake_attribute.clone() };
+
+    HistoryPackage { name, action, previous_version, current_version, flake_url, flake_attribute }
   }
 }
 
 impl From<&str> for HistoryPackage {
   fn from(value: &str) -> Self {
-    return HistoryPackage::from(value.to_string());
+    HistoryPackage::from(value.to_string())
   }
 }
 
 impl Clone for HistoryPackage {
   fn clone(&self) -> Self {
     HistoryPackage {
+      name: self.name.clone(),
       action: self.action.clone(),
 
       previous_version: self.previous_version.clone(),
@@ -139,31 +169,70 @@ impl Clone for HistoryPackage {
   }
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HistoryVersion {
+  pub date: Option<String>,
+  #[serde(rename = "storePath")]
+  pub store_path: Option<String>,
+  pub changes: Vec<HistoryPackage>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct HistoryVersions {
-  versions: BTreeMap<usize, Vec<HistoryPackage>>,
+  versions: BTreeMap<usize, HistoryVersion>,
+  #[serde(skip)]
+  active: Option<usize>,
 }
 
 impl HistoryVersions {
-  pub fn new(cli: &Cli) -> Self {
-    let mut version_map = BTreeMap::new();
+  /// Diffs the manifests of the profile's generations, falling back to `nix profile history`
+  /// when the generations cannot be read directly.
+  pub fn new(nix: &dyn NixBackend) -> Result<Self, Error> {
+    let generations = nix.profile_generations();
+    if let Some(versions) = generations.as_deref().and_then(HistoryVersions::from_generations) {
+      return Ok(versions);
+    }
+
+    let mut versions = HistoryVersions::parse(&nix.profile_history()?)?;
+    versions.active = nix.profile_link().map(|link| link.active);
 
-    let profile = cli.profile.clone().map(|profile| {
-      if !profile.is_empty() {
-        return format!("--profile {profile}");
+    // nix only prints the day, so take the time from the links that are still there
+    for generation in generations.iter().flatten() {
+      if let Some(version) = versions.versions.get_mut(&generation.number) {
+        version.date = Some(format_timestamp(generation.created));
       }
+    }
+
+    Ok(versions)
+  }
+
+  /// `None` when any manifest is missing or in a format `PackageListing` does not know.
+  fn from_generations(generations: &[GenerationLink]) -> Option<Self> {
+    let mut versions = BTreeMap::new();
+    let mut previous = PackageListing::default();
 
-      return profile;
-    }).unwrap_or_else(|| String::new());
+    for generation in generations {
+      let listing = PackageListing::from_manifest(generation.manifest.as_deref()?)?;
 
-    let history_output = catch_output(format!("nix profile history {profile}"), true);
-    if let Err(err) = history_output {
-      Operations::throw_if_needed(Err(err));
+      versions.insert(generation.number, HistoryVersion {
+        date: Some(format_timestamp(generation.created)),
+        store_path: Some(generation.store_path.clone()),
+        changes: HistoryPackage::diff(&previous, &listing),
+      });
+
+      previous = listing;
     }
-    let history_output = history_output.unwrap();
+
+    let active = generations.iter().find(|generation| generation.active).map(|generation| generation.number);
+    Some(HistoryVersions { versions, active })
+  }
+
+  /// Scrapes the human readable output of `nix profile history`.
+  fn parse(history_output: &str) -> Result<Self, Error> {
+    let mut version_map = BTreeMap::new();
 
     let mut current_version_index: usize = 0;
-    let mut current_version_list = Vec::new();
+    let mut current_version = HistoryVersion { date: None, store_path: None, changes: Vec::new() };
 
     for line in history_output.lines() {
       let line = strip_ansi_string(line.trim()).to_string();
@@ -173,26 +242,21 @@ impl HistoryVersions {
       }
 
       if line.starts_with("Version") {
-        let version_number = line.split_whitespace().skip(1).next();
-        if let Some(current_version) = version_number {
-          let current_version = current_version.parse::<usize>();
-          if let Ok(version_number) = current_version {
-
-            let previous_version = current_version_list.iter().cloned();
-            let previous_version = previous_version.collect::<Vec<HistoryPackage>>();
-
-            version_map.insert(current_version_index, previous_version);
-            if !current_version_list.is_empty() {
-              current_version_list.clear();
-            }
-
-            current_version_index = version_number;
-          } else {
-            Operations::throw_if_needed(Err(Error::Unknown {
-              code: 1,
-              message: format!("failed to parse version number: {version_number:?}")
-            }));
+        let version_number = line.split_whitespace().nth(1);
+        if let Some(version_number) = version_number {
+          let version_number = version_number.parse::<usize>().map_err(|_| Error::Unknown {
+            code: 1,
+            message: format!("failed to parse version number: {version_number:?}")
+          })?;
+
+          if current_version_index > 0 {
+            version_map.insert(current_version_index, current_version.clone());
           }
+
+          // midnight keeps the day nix prints in the format of the timestamps read from generation links
+          let date = line.split_once('(').and_then(|(_, rest)| rest.split_once(')')).map(|(date, _)| format!("{date}T00:00:00Z"));
+          current_version = HistoryVersion { date, store_path: None, changes: Vec::new() };
+          current_version_index = version_number;
         } else {
           break;
         }
@@ -203,29 +267,88 @@ impl HistoryVersions {
       let package = HistoryPackage::from(line);
 
       if !package.action.eq(&HistoryActions::None) {
-        current_version_list.push(package);
+        current_version.changes.push(package);
       }
     }
 
-    if !current_version_list.is_empty() {
-      let previous_version = current_version_list.iter().cloned();
-      let previous_version = previous_version.collect::<Vec<HistoryPackage>>();
-
-      version_map.insert(current_version_index, previous_version);
-      if !current_version_list.is_empty() {
-        current_version_list.clear();
-      }
+    if current_version_index > 0 {
+      version_map.insert(current_version_index, current_version);
     }
 
-    HistoryVersions { versions: version_map }
+    Ok(HistoryVersions { versions: version_map, active: None })
   }
 
-  pub fn iter(&self) -> Iter<'_, usize, Vec<HistoryPackage>> {
+  pub fn iter(&self) -> Iter<'_, usize, HistoryVersion> {
     self.versions.iter()
   }
 
-  #[allow(dead_code)]
-  pub fn get(&self, version: usize) -> Option<&Vec<HistoryPackage>> {
+  /// The generation the profile points to, or the newest one when that is unknown.
+  pub fn current(&self) -> Option<usize> {
+    self.active.or_else(|| self.versions.keys().next_back().copied()).filter(|version| *version > 0)
+  }
+
+  /// Names of the elements touched by the generations between `from` and `to`.
+  pub fn changed_between(&self, from: usize, to: usize) -> Vec<String> {
+    if from == to {
+      return Vec::new();
+    }
+
+    let mut names: Vec<String> = self.versions.range(from.min(to) + 1..=from.max(to))
+      .flat_map(|(_, version)| version.changes.iter())
+      .filter(|change| change.action != HistoryActions::None)
+      .map(|change| change.name.clone())
+      .collect();
+
+    names.sort();
+    names.dedup();
+    names
+  }
+
+  pub fn get(&self, version: usize) -> Option<&HistoryVersion> {
     self.versions.get(&version)
   }
+
+  /// Net changes from generation `from` to `to`, folding the generations in between so that
+  /// intermediate churn collapses; `from` may be newer than `to`.
+  pub fn diff(&self, from: usize, to: usize) -> Result<HistoryDiff, Error> {
+    if let Some(missing) = [from, to].into_iter().find(|generation| !self.versions.contains_key(generation)) {
+      return Err(Error::Unknown { code: 1, message: format!("generation {missing} does not exist") });
+    }
+
+    if from == to {
+      return Ok(HistoryDiff { from, to, changes: Vec::new() });
+    }
+
+    let mut net: BTreeMap<String, HistoryPackage> = BTreeMap::new();
+    for change in self.versions.range(from.min(to) + 1..=from.max(to)).flat_map(|(_, version)| version.changes.iter()) {
+      let entry = net.entry(change.name.clone()).or_insert_with(|| change.clone());
+      entry.current_version = change.current_version.clone();
+      entry.flake_url = change.flake_url.clone();
+      entry.flake_attribute = change.flake_attribute.clone();
+    }
+
+    let changes = net.into_values().filter_map(|mut change| {
+      if from > to {
+        std::mem::swap(&mut change.previous_version, &mut change.current_version);
+      }
+
+      change.action = match (&change.previous_version, &change.current_version) {
+        (None, Some(_)) => HistoryActions::Added,
+        (Some(_), None) => HistoryActions::Removed,
+        (Some(previous), Some(current)) if previous != current => HistoryActions::Upgraded,
+        _ => return None,
+      };
+
+      Some(change)
+    }).collect();
+
+    Ok(HistoryDiff { from, to, changes })
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryDiff {
+  pub from: usize,
+  pub to: usize,
+  pub changes: Vec<HistoryPackage>,
 }
@@ -0,0 +1,55 @@
+use std::{collections::BTreeSet, fs::{create_dir_all, read_to_string, write}, path::PathBuf};
+
+use crate::{Cli, error::Error};
+
+/// Packages `-Su` leaves alone, from the `hold` config key and `-D --hold`.
+#[derive(Debug, Default)]
+pub struct HoldList {
+  configured: BTreeSet<String>,
+  managed: BTreeSet<String>,
+  path: Option<PathBuf>,
+}
+
+impl HoldList {
+  pub fn new(cli: &Cli) -> Self {
+    let path = cli.config.state_dir.as_ref().map(|dir| dir.join("hold"));
+    let configured = cli.config.hold.iter().flatten().cloned().collect();
+    let managed = path.as_ref()
+      .and_then(|path| read_to_string(path).ok())
+      .map(|content| content.lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from).collect())
+      .unwrap_or_default();
+
+    HoldList { configured, managed, path }
+  }
+
+  pub fn contains(&self, name: &str) -> bool {
+    self.configured.contains(name) || self.managed.contains(name)
+  }
+
+  pub fn is_configured(&self, name: &str) -> bool {
+    self.configured.contains(name)
+  }
+
+  pub fn iter(&self) -> impl Iterator<Item = &String> {
+    self.configured.union(&self.managed)
+  }
+
+  pub fn hold(&mut self, name: &str) -> bool {
+    self.managed.insert(name.to_string())
+  }
+
+  pub fn unhold(&mut self, name: &str) -> bool {
+    self.managed.remove(name)
+  }
+
+  pub fn save(&self) -> Result<(), Error> {
+    let path = self.path.as_ref().ok_or_else(|| Error::Unknown { code: 1, message: "cannot determine the state directory".to_string() })?;
+
+    let mut content = self.managed.iter().cloned().collect::<Vec<String>>().join("\n");
+    content.push('\n');
+
+    path.parent().map(create_dir_all).unwrap_or(Ok(()))
+      .and_then(|_| write(path, content))
+      .map_err(|err| Error::Unknown { code: 1, message: format!("failed to write '{}': {err}", path.display()) })
+  }
+}
@@ -0,0 +1,159 @@
+use std::{fs::{create_dir_all, read_to_string, write}, path::{Path, PathBuf}};
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{CLI_NAME, backend::NixBackend, config::xdg_home, error::Error, operations::Operations};
+
+/// Collects every derivation below a package set the way `nix search` does, skipping anything that fails to evaluate.
+static INDEX_EXPRESSION: &str = r#"pkgs:
+  let
+    try = value: let result = builtins.tryEval (builtins.deepSeq value value); in if result.success then result.value else null;
+    isDerivation = value: (builtins.tryEval (builtins.isAttrs value && (value.type or null) == "derivation")).value == true;
+    shouldRecurse = value: (builtins.tryEval (builtins.isAttrs value && (value.recurseForDerivations or false))).value == true;
+    entry = path: pkg: try {
+      attrPath = builtins.concatStringsSep "." path;
+      pname = pkg.pname or (builtins.parseDrvName pkg.name).name;
+      version = pkg.version or (builtins.parseDrvName pkg.name).version;
+      description = pkg.meta.description or "";
+      mainProgram = pkg.meta.mainProgram or null;
+    };
+    collect = prefix: set: builtins.concatLists (map (name:
+      let
+        attempt = builtins.tryEval set.${name};
+        path = prefix ++ [ name ];
+      in
+        if !attempt.success then [ ]
+        else if isDerivation attempt.value then (let value = entry path attempt.value; in if value == null then [ ] else [ value ])
+        else if shouldRecurse attempt.value then collect path attempt.value
+        else [ ]
+    ) (builtins.attrNames set));
+  in collect [ ] pkgs"#;
+
+#[derive(Debug, Deserialize)]
+struct LockedRef {
+  rev: Option<String>,
+  #[serde(rename = "narHash")]
+  nar_hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FlakeMetadata {
+  #[serde(rename = "lockedUrl")]
+  locked_url: Option<String>,
+  url: Option<String>,
+  locked: Option<LockedRef>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IndexEntry {
+  #[serde(rename = "attrPath")]
+  pub attr_path: String,
+  pub pname: String,
+  pub version: String,
+  pub description: String,
+  #[serde(rename = "mainProgram")]
+  pub main_program: Option<String>,
+}
+
+/// Every package of one flake at one locked revision, cached under `$XDG_CACHE_HOME/nichts/index`.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SearchIndex {
+  pub flake: String,
+  pub revision: String,
+  /// Flake output the attribute paths are relative to, e.g. `legacyPackages.x86_64-linux`.
+  pub prefix: String,
+  pub packages: Vec<IndexEntry>,
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+  xdg_home("XDG_CACHE_HOME", ".cache").map(|path| path.join(CLI_NAME).join("index"))
+}
+
+/// Compiles each pattern as a case-insensitive regex, matching it literally when it is not a valid one.
+pub fn compile_patterns(patterns: &[String]) -> Vec<Regex> {
+  patterns.iter().filter_map(|pattern| {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+      .or_else(|_| RegexBuilder::new(&regex::escape(pattern)).case_insensitive(true).build())
+      .ok()
+  }).collect()
+}
+
+impl SearchIndex {
+  fn file(cache: &Path, flake: &str) -> PathBuf {
+    let name: String = flake.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect();
+    cache.join(format!("{name}.json"))
+  }
+
+  fn read(file: &Path) -> Option<Self> {
+    read_to_string(file).ok().and_then(|content| serde_json::from_str::<SearchIndex>(&content).ok())
+  }
+
+  /// Loads the cached index of `flake` without touching the network. With `refresh`, or when there is no
+  /// index yet, the flake's locked revision is resolved and the index is rebuilt if the revision has moved.
+  ///
+  /// When the revision cannot be determined (e.g. offline), any cached index is used as is.
+  pub fn load(nix: &dyn NixBackend, flake: &str, cache: Option<&Path>, refresh: bool) -> Result<Self, Error> {
+    let file = cache.map(|cache| SearchIndex::file(cache, flake));
+    let cached = match file.as_deref().and_then(SearchIndex::read) {
+      Some(cached) if !refresh => return Ok(cached),
+      cached => cached,
+    };
+
+    let metadata = nix.flake_metadata(flake)
+      .and_then(|json_output| serde_json::from_str::<FlakeMetadata>(&json_output)
+        .map_err(|err| Error::Unknown { code: 1, message: format!("failed to parse flake metadata: {err}") }));
+
+    let metadata = match metadata {
+      Ok(metadata) => metadata,
+      Err(err) => return cached.ok_or(err),
+    };
+
+    let locked = metadata.locked.as_ref();
+    let locked_url = metadata.locked_url.or(metadata.url).unwrap_or_else(|| flake.to_string());
+    let revision = locked.and_then(|locked| locked.rev.clone().or(locked.nar_hash.clone())).unwrap_or_else(|| locked_url.clone());
+
+    if let Some(cached) = cached && cached.revision == revision {
+      return Ok(cached);
+    }
+
+    eprintln!("building search index for {flake}, this may take a while...");
+    let index = SearchIndex::build(nix, flake, &locked_url, revision)?;
+
+    if let Some(file) = file {
+      let written = file.parent().map(create_dir_all).unwrap_or(Ok(()))
+        .and_then(|_| write(&file, serde_json::to_string(&index).unwrap_or_default()));
+
+      if let Err(err) = written {
+        Operations::show_warning(format!("failed to write search index '{}': {err}", file.display()));
+      }
+    }
+
+    Ok(index)
+  }
+
+  fn build(nix: &dyn NixBackend, flake: &str, locked_url: &str, revision: String) -> Result<Self, Error> {
+    let system = nix.current_system()?;
+    let mut last_error = Error::NoPackageFound;
+
+    for prefix in [format!("legacyPackages.{system}"), format!("packages.{system}")] {
+      let packages = nix.eval_json(&format!("{locked_url}#{prefix}"), INDEX_EXPRESSION)
+        .and_then(|json_output| serde_json::from_str::<Vec<IndexEntry>>(&json_output)
+          .map_err(|err| Error::Unknown { code: 1, message: format!("failed to parse search index: {err}") }));
+
+      match packages {
+        Ok(packages) => return Ok(SearchIndex { flake: flake.to_string(), revision, prefix, packages }),
+        Err(err) => last_error = err,
+      }
+    }
+
+    Err(last_error)
+  }
+
+  /// Entries where every pattern matches the attribute path, pname or description.
+  pub fn matches<'a>(&'a self, patterns: &'a [Regex]) -> impl Iterator<Item = &'a IndexEntry> {
+    self.packages.iter().filter(|entry| patterns.iter().all(|pattern| {
+      pattern.is_match(&entry.attr_path) || pattern.is_match(&entry.pname) || pattern.is_match(&entry.description)
+    }))
+  }
+}
@@ -0,0 +1,176 @@
+use std::{
+  collections::BTreeSet, fmt::Display, fs::{OpenOptions, create_dir_all, read_to_string}, io::{ErrorKind, Write},
+  path::PathBuf, time::{SystemTime, UNIX_EPOCH}
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+  CLI_NAME, Cli, api::{history::{HistoryVersions, UNSET_VERSION}, query::PackageListing}, backend::NixBackend,
+  error::Error, hooks::HookOperation
+};
+
+/// Formats seconds since the epoch as an ISO 8601 UTC timestamp, which also sorts chronologically as a string.
+pub fn format_timestamp(seconds: u64) -> String {
+  let days = (seconds / 86400) as i64;
+  let time = seconds % 86400;
+
+  // civil date from days since 1970-01-01, see http://howardhinnant.github.io/date_algorithms.html
+  let shifted = days + 719468;
+  let era = shifted.div_euclid(146097);
+  let day_of_era = shifted.rem_euclid(146097);
+  let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+  let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+  let month_index = (5 * day_of_year + 2) / 153;
+  let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+  let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+  let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+  format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}Z", hour = time / 3600, minute = time % 3600 / 60, second = time % 60)
+}
+
+/// The profile as seen before or after a transaction.
+#[derive(Debug, Default)]
+pub struct LogSnapshot {
+  generation: Option<usize>,
+  versions: Vec<(String, String)>,
+}
+
+impl LogSnapshot {
+  pub fn take(nix: &dyn NixBackend) -> Self {
+    let generation = HistoryVersions::new(nix).ok().and_then(|versions| versions.current());
+    let versions = PackageListing::new(nix)
+      .map(|listing| listing.to_vec().into_iter().map(|(name, package)| (name.clone(), package.version.clone())).collect())
+      .unwrap_or_default();
+
+    LogSnapshot { generation, versions }
+  }
+
+  fn version(&self, name: &str) -> Option<String> {
+    self.versions.iter().find(|(element, _)| element == name).map(|(_, version)| version.clone())
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogChange {
+  pub name: String,
+  #[serde(rename = "oldVersion")]
+  pub old_version: Option<String>,
+  #[serde(rename = "newVersion")]
+  pub new_version: Option<String>,
+}
+
+impl Display for LogChange {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match (&self.old_version, &self.new_version) {
+      (None, Some(new)) => write!(f, "installed {name} ({new})", name = self.name),
+      (Some(old), None) => write!(f, "removed {name} ({old})", name = self.name),
+      (old, new) => write!(f, "upgraded {name} ({old} -> {new})", name = self.name,
+        old = old.as_deref().unwrap_or(UNSET_VERSION), new = new.as_deref().unwrap_or(UNSET_VERSION)
+      ),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+  pub timestamp: String,
+  pub operation: HookOperation,
+  pub flags: Vec<String>,
+  pub packages: Vec<LogChange>,
+  #[serde(rename = "generationBefore")]
+  pub generation_before: Option<usize>,
+  #[serde(rename = "generationAfter")]
+  pub generation_after: Option<usize>,
+  pub status: i32,
+}
+
+impl LogEntry {
+  /// Compares both snapshots to find the elements the transaction added, removed or changed.
+  pub fn new(operation: HookOperation, flags: Vec<String>, before: &LogSnapshot, after: &LogSnapshot, status: i32) -> Self {
+    let names: BTreeSet<&String> = before.versions.iter().chain(after.versions.iter()).map(|(name, _)| name).collect();
+    let packages = names.into_iter()
+      .map(|name| LogChange { name: name.clone(), old_version: before.version(name), new_version: after.version(name) })
+      .filter(|change| change.old_version != change.new_version)
+      .collect();
+
+    let seconds = SystemTime::now().duration_since(UNIX_EPOCH).map(|duration| duration.as_secs()).unwrap_or(0);
+
+    LogEntry {
+      timestamp: format_timestamp(seconds),
+      operation,
+      flags,
+      packages,
+      generation_before: before.generation,
+      generation_after: after.generation,
+      status,
+    }
+  }
+
+  pub fn touches(&self, package: &str) -> bool {
+    self.packages.iter().any(|change| change.name == package)
+  }
+
+  /// Whether the entry falls between `since` and `until`, both inclusive prefixes like `2025-01` or `2025-01-31`.
+  pub fn within(&self, since: Option<&str>, until: Option<&str>) -> bool {
+    let prefix = |bound: &str| &self.timestamp[..bound.len().min(self.timestamp.len())];
+
+    since.is_none_or(|since| prefix(since) >= since) && until.is_none_or(|until| prefix(until) <= until)
+  }
+}
+
+impl Display for LogEntry {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let generation = |generation: Option<usize>| generation.map(|generation| generation.to_string()).unwrap_or(UNSET_VERSION.to_string());
+    let prefix = format!("[{timestamp}] [{operation}]", timestamp = self.timestamp, operation = self.operation);
+
+    writeln!(f, "{prefix} running '{CLI_NAME} {flags}'", flags = self.flags.join(" "))?;
+    for change in &self.packages {
+      writeln!(f, "{prefix} {change}")?;
+    }
+
+    let outcome = if self.status == 0 { "completed".to_string() } else { format!("failed with exit status {}", self.status) };
+    write!(f, "{prefix} transaction {outcome} (generation {before} -> {after})",
+      before = generation(self.generation_before), after = generation(self.generation_after)
+    )
+  }
+}
+
+pub struct TransactionLog;
+
+impl TransactionLog {
+  pub fn path(cli: &Cli) -> Option<PathBuf> {
+    cli.config.state_dir.as_ref().map(|dir| dir.join(format!("{CLI_NAME}.log")))
+  }
+
+  /// Appends `entry` as one JSON line.
+  pub fn append(cli: &Cli, entry: &LogEntry) -> Result<(), Error> {
+    let Some(path) = TransactionLog::path(cli) else {
+      return Ok(());
+    };
+
+    let line = serde_json::to_string(entry).map_err(|_| Error::FailedJsonSerialization)?;
+
+    path.parent().map(create_dir_all).unwrap_or(Ok(()))
+      .and_then(|_| OpenOptions::new().create(true).append(true).open(&path))
+      .and_then(|mut file| writeln!(file, "{line}"))
+      .map_err(|err| Error::Unknown { code: 1, message: format!("failed to write '{}': {err}", path.display()) })
+  }
+
+  pub fn read(cli: &Cli) -> Result<Vec<LogEntry>, Error> {
+    let Some(path) = TransactionLog::path(cli) else {
+      return Ok(Vec::new());
+    };
+
+    match read_to_string(&path) {
+      Ok(content) => Ok(TransactionLog::parse(&content)),
+      Err(err) if err.kind() == ErrorKind::NotFound => Ok(Vec::new()),
+      Err(err) => Err(Error::Unknown { code: 1, message: format!("failed to read '{}': {err}", path.display()) }),
+    }
+  }
+
+  /// Parses the JSON lines of the log, skipping lines that are not valid entries.
+  pub fn parse(content: &str) -> Vec<LogEntry> {
+    content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect()
+  }
+}
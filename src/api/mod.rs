@@ -1,2 +1,11 @@
+pub mod closure;
+pub mod files;
 pub mod history;
+pub mod hold;
+pub mod index;
+pub mod log;
 pub mod query;
+pub mod remote;
+pub mod search;
+pub mod store;
+pub mod upgrades;
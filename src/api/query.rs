@@ -1,8 +1,8 @@
-use std::{collections::{BTreeMap, btree_map::Keys}, fs::{metadata, read_dir}, io, path::Path};
+use std::{collections::{BTreeMap, btree_map::Keys}, path::{Path, PathBuf}};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{Cli, command::catch_output, error::Error, operations::Operations};
+use crate::{api::{files::list_files, store::StorePaths}, backend::NixBackend, error::Error};
 
 fn version_default() -> String {
   "latest".to_string()
@@ -11,108 +11,201 @@ fn version_default() -> String {
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Package {
   pub active: bool,
-  #[serde(rename = "attrPath")]
+  #[serde(rename = "attrPath", default)]
   pub attr_path: String,
-  #[serde(rename = "originalUrl")]
+  #[serde(rename = "originalUrl", default)]
   pub original_url: String,
   pub outputs: Option<String>,
   pub priority: isize,
   #[serde(rename = "storePaths")]
   pub store_paths: Vec<String>,
+  #[serde(default)]
   pub url: String,
 
   pub homepage: Option<String>,
   pub installed_size: Option<u64>,
+  pub closure_size: Option<u64>,
   #[serde(default = "version_default")]
   pub version: String,
 }
 
-fn get_dir_size(path: &Path) -> io::Result<u64> {
-  let mut size = 0;
-  for entry in read_dir(path)? {
-    let entry = entry?;
-    let path = entry.path();
+/// Indirect flake references are recorded as `flake:<id>` by nix, so `nixpkgs` and `flake:nixpkgs` are the same source.
+fn normalize_flake(url: &str) -> &str {
+  url.strip_prefix("flake:").unwrap_or(url)
+}
+
+impl Package {
+  /// Whether a version could be read from the element's store path.
+  pub fn has_version(&self) -> bool {
+    self.version != version_default()
+  }
+
+  pub fn is_from(&self, flake: &str) -> bool {
+    normalize_flake(&self.original_url) == normalize_flake(flake)
+  }
 
-    size += match () {
-      _ if path.is_file() => metadata(&path)?.len(),
-      _ if path.is_dir() => get_dir_size(&path)?,
-      _ => 0
+  /// The name nix gives an element of a version 2 manifest, which stores elements as a list.
+  /// Like nix' `getNameFromURL`, this is the `dir` parameter of its flake, the last attribute unless
+  /// it is a default package, the repository or last path segment, and else the package name of its first store path.
+  fn manifest_name(&self) -> String {
+    let (url, query) = self.original_url.split_once('?').unwrap_or((&self.original_url, ""));
+    if let Some(dir) = query.split('&').find_map(|parameter| parameter.strip_prefix("dir=")).filter(|dir| !dir.is_empty()) {
+      return dir.to_string();
+    }
+
+    let (prefix, attribute) = self.attr_path.rsplit_once('.').unwrap_or(("", &self.attr_path));
+    if !attribute.is_empty() && attribute != "default" && prefix != "defaultPackage" {
+      return attribute.to_string();
+    }
+
+    let (scheme, path) = url.split_once(':').unwrap_or(("", url));
+    let segment = if matches!(scheme, "github" | "gitlab" | "sourcehut") {
+      path.split('/').nth(1)
+    } else {
+      // skip the authority of URLs like `git+https://host/owner/repo`
+      let path = path.strip_prefix("//").map(|rest| rest.split_once('/').map(|(_, path)| path).unwrap_or("")).unwrap_or(path);
+      path.trim_end_matches('/').rsplit_once('/').map(|(_, segment)| segment)
     };
+    if let Some(segment) = segment.filter(|segment| !segment.is_empty() && *segment != ".") {
+      return segment.to_string();
+    }
+
+    let file_name = self.store_paths.first()
+      .and_then(|store_path| Path::new(store_path).file_name())
+      .map(|file_name| file_name.to_string_lossy().to_string())
+      .unwrap_or_default();
+    let name = file_name.split_once('-').map(|(_, name)| name).unwrap_or(&file_name);
+
+    // like nix' DrvName, the version starts at the first dash followed by a digit
+    name.char_indices()
+      .find(|(index, char)| *char == '-' && name[index + 1..].starts_with(|next: char| next.is_ascii_digit()))
+      .map(|(index, _)| name[..index].to_string())
+      .unwrap_or(name.to_string())
   }
-  Ok(size)
-}
 
-impl Package {
   fn store_path(&self) -> Option<String> {
     let mut paths = self.store_paths.clone();
     if !self.store_paths.len().eq(&1) {
       paths.retain(|path| !path.ends_with("-man"));
     }
 
-    if let Some(store_path) = paths.get(0) {
-      return Some(store_path.clone());
-    }
+    paths.first().cloned()
+  }
+
+  /// Files of every output merged the way the profile exposes them.
+  pub fn files(&self, bin_only: bool) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = self.store_paths.iter().flat_map(|store_path| list_files(Path::new(store_path), bin_only)).collect();
+    files.sort();
+    files.dedup();
+    files
+  }
 
-    None
+  pub fn owns(&self, path: &Path) -> bool {
+    self.store_paths.iter().any(|store_path| path.starts_with(store_path))
   }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct PackageListing {
   elements: BTreeMap<String, Package>
 }
 
+#[derive(Deserialize)]
+struct ManifestV2 {
+  elements: Vec<Package>,
+}
+
+#[derive(Deserialize)]
+struct ManifestVersion {
+  version: u64,
+}
+
 impl PackageListing {
-  pub fn new(cli: &Cli) -> Self {
-    let json_output = catch_output(cli.prepare_command("nix profile list --json"), false);
-    if let Err(err) = json_output {
-      Operations::throw_if_needed(Err(err));
+  /// Reads the profile's `manifest.json` when possible and only asks `nix profile list` otherwise.
+  pub fn new(nix: &dyn NixBackend) -> Result<Self, Error> {
+    if let Some(listing) = nix.profile_manifest().and_then(|manifest| PackageListing::from_manifest(&manifest)) {
+      return Ok(listing);
     }
-    let json_output = json_output.unwrap();
 
-    let listing = serde_json::from_str::<PackageListing>(&json_output);
-    if let Err(err) = listing {
-      Operations::throw_if_needed(Err(Error::Unknown { code: 1, message: err.to_string() }));
-    }
+    let mut listing = serde_json::from_str::<PackageListing>(&nix.profile_list()?)
+      .map_err(|err| Error::Unknown { code: 1, message: err.to_string() })?;
 
-    let mut listing = listing.unwrap();
+    listing.load_versions();
+    Ok(listing)
+  }
 
-    listing.elements.iter_mut().for_each(|(key, package)| {
-      let mut paths = package.store_paths.clone();
-      if !package.store_paths.len().eq(&1) {
-        paths.retain(|path| !path.ends_with("-man"));
-      }
+  /// Parses a version 2 or 3 manifest, `None` for any other format.
+  pub fn from_manifest(manifest: &str) -> Option<Self> {
+    let mut listing = match serde_json::from_str::<ManifestVersion>(manifest).ok()?.version {
+      3 => serde_json::from_str::<PackageListing>(manifest).ok()?,
+      2 => {
+        let mut elements = BTreeMap::new();
+
+        for package in serde_json::from_str::<ManifestV2>(manifest).ok()?.elements {
+          let name = package.manifest_name();
+
+          let mut unique = name.clone();
+          let mut suffix = 0;
+          while elements.contains_key(&unique) {
+            suffix += 1;
+            unique = format!("{name}-{suffix}");
+          }
+
+          elements.insert(unique, package);
+        }
 
+        PackageListing { elements }
+      },
+      _ => return None,
+    };
+
+    listing.load_versions();
+    Some(listing)
+  }
+
+  fn load_versions(&mut self) {
+    self.elements.iter_mut().for_each(|(key, package)| {
       if let Some(store_path) = package.store_path() {
         let path = Path::new(&store_path);
 
-        if let Some(filename) = path.file_name() {
-          if let Some(filename_str) = filename.to_str() {
-            if let Some(index) = filename_str.find(key) {
-              let version = &filename_str[(index.saturating_add(key.len()))..];
+        if let Some(filename_str) = path.file_name().and_then(|filename| filename.to_str())
+          && let Some(index) = filename_str.find(key.as_str()) {
+          let version = &filename_str[(index.saturating_add(key.len()))..];
 
-              if !version.len().eq(&0) {
-                package.version = version[1..].to_string();
-              }
-            }
+          if !version.is_empty() {
+            package.version = version[1..].to_string();
           }
         }
-
-        let dir_size = get_dir_size(path);
-        if let Ok(size) = dir_size {
-          package.installed_size = Some(size);
-        }
       }
     });
+  }
+
+  /// Fills in NAR and closure sizes for every element with one batched `nix path-info` call.
+  pub fn load_sizes(&mut self, nix: &dyn NixBackend) -> Result<(), Error> {
+    let paths: Vec<String> = self.elements.values().flat_map(|package| package.store_paths.iter().cloned()).collect();
+    let store_paths = StorePaths::new(nix, &paths, true)?;
+
+    self.elements.values_mut().for_each(|package| {
+      package.installed_size = Some(store_paths.nar_size(&package.store_paths));
+      package.closure_size = Some(store_paths.closure_size(&package.store_paths));
+    });
 
-    listing
+    Ok(())
   }
 
   pub fn retain<P: FnMut(&str, &Package) -> bool>(&mut self, mut predicate: P) -> &mut Self {
-    self.elements.retain(|key, value| !predicate(key, value));
+    self.elements.retain(|key, value| predicate(key, value));
     self
   }
 
+  pub fn owner(&self, path: &Path) -> Option<(&String, &Package)> {
+    self.elements.iter().find(|(_, package)| package.owns(path))
+  }
+
+  pub fn get(&self, name: &str) -> Option<&Package> {
+    self.elements.get(name)
+  }
+
   pub fn keys(&self) -> Keys<'_, String, Package> {
     self.elements.keys()
   }
@@ -130,17 +223,13 @@ impl PackageListing {
     self.elements.iter().any(|(key, value)| predicate(key, value))
   }
 
-  pub fn any_mut<P: FnMut(&String, &Package) -> bool>(&mut self, mut predicate: P) -> bool {
-    self.elements.iter_mut().any(|(key, value)| predicate(key, value))
-  }
-
   #[allow(dead_code)]
   pub fn every<P: Fn(&String, &Package) -> bool>(&self, predicate: P) -> Vec<(&String, &Package)> {
     self.elements.iter().filter(|(key, value)| !predicate(key, value)).collect()
   }
 
   #[allow(dead_code)]
-  pub fn for_each<P: Fn(&String, &Package) -> ()>(&self, predicate: P) -> () {
+  pub fn for_each<P: Fn(&String, &Package)>(&self, predicate: P) {
     self.elements.iter().for_each(|(key, value)| {
       predicate(key, value);
     });
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{backend::NixBackend, error::Error};
+
+/// Reduces a derivation to the metadata shown by `-Si`, normalizing the many shapes `meta` fields can take.
+static META_EXPRESSION: &str = r#"pkg:
+  let
+    meta = pkg.meta or { };
+    toList = value: if builtins.isList value then value else [ value ];
+  in {
+    name = pkg.pname or pkg.name or null;
+    version = pkg.version or null;
+    outputs = pkg.outputs or [ "out" ];
+    description = meta.description or null;
+    homepage = toList (meta.homepage or [ ]);
+    license = map (license: if builtins.isAttrs license then license.spdxId or license.shortName or license.fullName or "unknown" else license) (toList (meta.license or [ ]));
+    platforms = builtins.filter builtins.isString (meta.platforms or [ ]);
+    maintainers = map (maintainer: maintainer.github or maintainer.name or "unknown") (meta.maintainers or [ ]);
+    mainProgram = meta.mainProgram or null;
+    broken = meta.broken or false;
+    unfree = meta.unfree or false;
+    insecure = meta.insecure or false;
+  }"#;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RemotePackage {
+  #[serde(default)]
+  pub flake: String,
+  #[serde(rename = "attrPath", default)]
+  pub attr_path: String,
+  pub name: Option<String>,
+  pub version: Option<String>,
+  pub outputs: Vec<String>,
+  pub description: Option<String>,
+  pub homepage: Vec<String>,
+  pub license: Vec<String>,
+  pub platforms: Vec<String>,
+  pub maintainers: Vec<String>,
+  #[serde(rename = "mainProgram")]
+  pub main_program: Option<String>,
+  pub broken: bool,
+  pub unfree: bool,
+  pub insecure: bool,
+}
+
+impl RemotePackage {
+  pub fn new(nix: &dyn NixBackend, flake: &str, attr_path: &str) -> Result<Self, Error> {
+    let json_output = nix.eval_json(&format!("{flake}#{attr_path}"), META_EXPRESSION)?;
+
+    let mut package = serde_json::from_str::<RemotePackage>(&json_output)
+      .map_err(|err| Error::Unknown { code: 1, message: format!("failed to parse metadata of '{attr_path}': {err}") })?;
+    package.flake = flake.to_string();
+    package.attr_path = attr_path.to_string();
+
+    Ok(package)
+  }
+}
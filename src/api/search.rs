@@ -0,0 +1,100 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{api::{index::{SearchIndex, cache_dir, compile_patterns}, query::PackageListing}, backend::NixBackend, error::Error, operations::Operations};
+
+#[derive(Debug, Deserialize)]
+struct SearchEntry {
+  #[serde(default)]
+  pname: String,
+  #[serde(default)]
+  version: String,
+  #[serde(default)]
+  description: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResult {
+  #[serde(rename = "attrPath")]
+  pub attr_path: String,
+  pub name: String,
+  pub pname: String,
+  pub version: String,
+  pub description: String,
+  #[serde(rename = "mainProgram")]
+  pub main_program: Option<String>,
+  /// Installed version, when the same flake attribute is already in the profile.
+  pub installed: Option<String>,
+}
+
+/// Strips the `legacyPackages.<system>.`/`packages.<system>.` prefix that `nix search` reports.
+pub fn short_attr_path(attr_path: &str) -> &str {
+  for prefix in ["legacyPackages.", "packages."] {
+    if let Some(rest) = attr_path.strip_prefix(prefix)
+      && let Some((_, name)) = rest.split_once('.') {
+      return name;
+    }
+  }
+
+  attr_path
+}
+
+fn installed_version(listing: &PackageListing, flake: &str, attr_path: &str) -> Option<String> {
+  listing.to_vec().into_iter()
+    .find(|(_, package)| package.is_from(flake) && package.attr_path == attr_path)
+    .map(|(_, package)| package.version.clone())
+}
+
+impl SearchResult {
+  /// Searches the local index of `flake`, falling back to `nix search` when no index can be built.
+  /// `refresh` checks whether the index is still current, see `SearchIndex::load`.
+  pub fn search(nix: &dyn NixBackend, flake: &str, patterns: &[String], listing: &PackageListing, refresh: bool) -> Result<Vec<Self>, Error> {
+    SearchResult::search_in(nix, flake, patterns, listing, cache_dir().as_deref(), refresh)
+  }
+
+  pub fn search_in(nix: &dyn NixBackend, flake: &str, patterns: &[String], listing: &PackageListing, cache: Option<&Path>, refresh: bool) -> Result<Vec<Self>, Error> {
+    match SearchIndex::load(nix, flake, cache, refresh) {
+      Ok(index) => {
+        let patterns = compile_patterns(patterns);
+
+        Ok(index.matches(&patterns).map(|entry| {
+          let attr_path = format!("{prefix}.{attribute}", prefix = index.prefix, attribute = entry.attr_path);
+
+          SearchResult {
+            name: entry.attr_path.clone(),
+            installed: installed_version(listing, flake, &attr_path),
+            attr_path,
+            pname: entry.pname.clone(),
+            version: entry.version.clone(),
+            description: entry.description.clone(),
+            main_program: entry.main_program.clone(),
+          }
+        }).collect())
+      },
+      Err(err) => {
+        Operations::show_warning(format!("search index unavailable ({err}), falling back to nix search"));
+        SearchResult::search_nix(nix, flake, patterns, listing)
+      }
+    }
+  }
+
+  fn search_nix(nix: &dyn NixBackend, flake: &str, patterns: &[String], listing: &PackageListing) -> Result<Vec<Self>, Error> {
+    let json_output = nix.search(flake, patterns)?;
+
+    let entries = serde_json::from_str::<BTreeMap<String, SearchEntry>>(&json_output)
+      .map_err(|err| Error::Unknown { code: 1, message: format!("failed to parse search results: {err}") })?;
+
+    Ok(entries.into_iter().map(|(attr_path, entry)| {
+      SearchResult {
+        name: short_attr_path(&attr_path).to_string(),
+        installed: installed_version(listing, flake, &attr_path),
+        attr_path,
+        pname: entry.pname,
+        version: entry.version,
+        description: entry.description,
+        main_program: None,
+      }
+    }).collect())
+  }
+}
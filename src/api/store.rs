@@ -0,0 +1,209 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use fast_strip_ansi::strip_ansi_string;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{api::query::{Package, PackageListing}, backend::NixBackend, error::Error};
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PathInfo {
+  #[serde(rename = "narSize", default)]
+  pub nar_size: u64,
+  #[serde(rename = "closureSize")]
+  pub closure_size: Option<u64>,
+  #[serde(default)]
+  pub references: Vec<String>,
+  /// Compressed size, only reported by binary cache stores.
+  #[serde(rename = "downloadSize")]
+  pub download_size: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct PathInfoEntry {
+  path: String,
+  #[serde(flatten)]
+  info: PathInfo,
+}
+
+/// Older nix versions emit a list of entries, newer ones an object keyed by store path.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum PathInfoOutput {
+  Map(BTreeMap<String, Option<PathInfo>>),
+  List(Vec<PathInfoEntry>),
+}
+
+#[derive(Debug, Default)]
+pub struct StorePaths {
+  paths: BTreeMap<String, PathInfo>,
+}
+
+impl StorePaths {
+  pub fn new(nix: &dyn NixBackend, paths: &[String], recursive: bool) -> Result<Self, Error> {
+    if paths.is_empty() {
+      return Ok(StorePaths::default());
+    }
+
+    let json_output = nix.path_info(paths, recursive)?;
+    StorePaths::parse(&json_output)
+  }
+
+  pub fn parse(json_output: &str) -> Result<Self, Error> {
+    let output = serde_json::from_str::<PathInfoOutput>(json_output)
+      .map_err(|err| Error::Unknown { code: 1, message: format!("failed to parse path info: {err}") })?;
+
+    let paths = match output {
+      PathInfoOutput::Map(map) => map.into_iter().filter_map(|(path, info)| info.map(|info| (path, info))).collect(),
+      PathInfoOutput::List(list) => list.into_iter().map(|entry| (entry.path, entry.info)).collect(),
+    };
+
+    Ok(StorePaths { paths })
+  }
+
+  pub fn paths(&self) -> impl Iterator<Item = &String> {
+    self.paths.keys()
+  }
+
+  pub fn get(&self, path: &str) -> Option<&PathInfo> {
+    self.paths.get(path)
+  }
+
+  pub fn closure(&self, roots: &[String]) -> BTreeSet<String> {
+    let mut closure = BTreeSet::new();
+    let mut pending: Vec<&String> = roots.iter().collect();
+
+    while let Some(path) = pending.pop() {
+      if !closure.insert(path.clone()) {
+        continue;
+      }
+
+      if let Some(info) = self.paths.get(path) {
+        pending.extend(info.references.iter());
+      }
+    }
+
+    closure
+  }
+
+  pub fn nar_size(&self, paths: &[String]) -> u64 {
+    paths.iter().filter_map(|path| self.get(path)).map(|info| info.nar_size).sum()
+  }
+
+  pub fn total_size(&self) -> u64 {
+    self.paths.values().map(|info| info.nar_size).sum()
+  }
+
+  pub fn closure_size(&self, roots: &[String]) -> u64 {
+    if let [root] = roots
+      && let Some(closure_size) = self.get(root).and_then(|info| info.closure_size) {
+      return closure_size;
+    }
+
+    self.closure(roots).iter().filter_map(|path| self.get(path)).map(|info| info.nar_size).sum()
+  }
+}
+
+#[derive(Debug, Serialize)]
+pub struct TransactionSize {
+  pub download: u64,
+  pub installed: u64,
+  pub net: i64,
+}
+
+impl TransactionSize {
+  pub fn new(nix: &dyn NixBackend, listing: &PackageListing, replaced: &[String], installables: &[String]) -> Result<Self, Error> {
+    let current_paths: Vec<String> = listing.to_vec().into_iter().flat_map(|(_, package)| package.store_paths.iter().cloned()).collect();
+    let kept_paths: Vec<String> = listing.to_vec().into_iter()
+      .filter(|(name, _)| !replaced.contains(name))
+      .flat_map(|(_, package)| package.store_paths.iter().cloned())
+      .collect();
+
+    let current = StorePaths::new(nix, &current_paths, true)?;
+    let incoming = StorePaths::parse(&nix.remote_path_info(installables)?)?;
+
+    let missing: Vec<&PathInfo> = incoming.paths()
+      .filter(|path| !nix.is_present(path))
+      .filter_map(|path| incoming.get(path))
+      .collect();
+
+    let download = missing.iter().map(|info| info.download_size.unwrap_or(info.nar_size)).sum();
+    let installed = missing.iter().map(|info| info.nar_size).sum();
+
+    let mut next_closure = current.closure(&kept_paths);
+    next_closure.extend(incoming.paths().cloned());
+
+    let next_size: u64 = next_closure.iter()
+      .filter_map(|path| current.get(path).or_else(|| incoming.get(path)))
+      .map(|info| info.nar_size)
+      .sum();
+    let current_size = current.closure_size(&current_paths);
+
+    Ok(TransactionSize { download, installed, net: next_size as i64 - current_size as i64 })
+  }
+}
+
+static VERIFY_CORRUPTED: i32 = 1;
+static VERIFY_INVALID: i32 = 4;
+
+#[derive(Debug, Serialize)]
+pub struct Integrity {
+  pub name: String,
+  pub closure: bool,
+  #[serde(rename = "storePaths")]
+  pub store_paths: usize,
+  pub missing: Vec<String>,
+  pub corrupted: Vec<String>,
+  pub invalid: Vec<String>,
+}
+
+impl Integrity {
+  pub fn check(nix: &dyn NixBackend, name: &str, package: &Package, closure: bool) -> Result<Self, Error> {
+    let (present, missing): (Vec<String>, Vec<String>) = package.store_paths.iter().cloned()
+      .partition(|path| nix.is_present(path));
+
+    let (status, errors) = if present.is_empty() { (0, String::new()) } else { nix.store_verify(&present, closure)? };
+    let (mut corrupted, mut invalid) = Integrity::parse(&errors);
+
+    // blame the checked paths themselves when nix failed without naming any path
+    if status & VERIFY_CORRUPTED != 0 && corrupted.is_empty() {
+      corrupted = present.clone();
+    }
+
+    if status & VERIFY_INVALID != 0 && invalid.is_empty() {
+      invalid = present;
+    }
+
+    Ok(Integrity {
+      name: name.to_string(),
+      closure,
+      store_paths: package.store_paths.len(),
+      missing,
+      corrupted,
+      invalid,
+    })
+  }
+
+  pub fn parse(errors: &str) -> (Vec<String>, Vec<String>) {
+    let path_pattern = Regex::new(r"path '([^']+)' (was modified|is not valid)").unwrap();
+    let (mut corrupted, mut invalid) = (Vec::new(), Vec::new());
+
+    for line in errors.lines() {
+      let line = strip_ansi_string(line).to_string();
+      let Some(captures) = path_pattern.captures(&line) else {
+        continue;
+      };
+
+      let list = if &captures[2] == "was modified" { &mut corrupted } else { &mut invalid };
+      if !list.contains(&captures[1].to_string()) {
+        list.push(captures[1].to_string());
+      }
+    }
+
+    (corrupted, invalid)
+  }
+
+  pub fn is_ok(&self) -> bool {
+    self.missing.is_empty() && self.corrupted.is_empty() && self.invalid.is_empty()
+  }
+}
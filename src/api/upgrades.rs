@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{api::{hold::HoldList, query::PackageListing}, backend::NixBackend, error::Error, operations::Operations};
+
+/// The version and every output path an element's source evaluates to now.
+static OUTPUTS_EXPRESSION: &str = r#"pkg: {
+    version = pkg.version or null;
+    outPaths = map (output: pkg.${output}.outPath) (pkg.outputs or [ "out" ]);
+  }"#;
+
+#[derive(Deserialize)]
+struct Evaluated {
+  version: Option<String>,
+  #[serde(rename = "outPaths")]
+  out_paths: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PendingUpgrade {
+  pub name: String,
+  #[serde(rename = "attrPath")]
+  pub attr_path: String,
+  #[serde(rename = "originalUrl")]
+  pub original_url: String,
+  #[serde(rename = "installedVersion")]
+  pub installed_version: Option<String>,
+  #[serde(rename = "availableVersion")]
+  pub available_version: Option<String>,
+  /// `false` when the source failed to evaluate, so whether it changed is unknown.
+  pub evaluated: bool,
+  pub held: bool,
+}
+
+impl PendingUpgrade {
+  /// Evaluates every element's source and keeps the ones whose store paths would change,
+  /// which includes rebuilds that keep the version. Elements that fail to evaluate are kept
+  /// as well, leaving it to `nix profile upgrade` to report why.
+  pub fn collect(nix: &dyn NixBackend, listing: &PackageListing, holds: &HoldList) -> Result<Vec<Self>, Error> {
+    let mut upgrades = Vec::new();
+
+    for (name, package) in listing.to_vec() {
+      let installable = format!("{flake}#{attribute}", flake = package.original_url, attribute = package.attr_path);
+
+      let evaluated = nix.eval_json(&installable, OUTPUTS_EXPRESSION)
+        .and_then(|json_output| serde_json::from_str::<Evaluated>(&json_output)
+          .map_err(|err| Error::Unknown { code: 1, message: err.to_string() }));
+
+      let available_version = match &evaluated {
+        Ok(evaluated) if package.store_paths.iter().all(|store_path| evaluated.out_paths.contains(store_path)) => continue,
+        Ok(evaluated) => evaluated.version.clone(),
+        Err(_) => {
+          Operations::show_warning(format!("failed to evaluate {installable}"));
+          None
+        },
+      };
+
+      upgrades.push(PendingUpgrade {
+        name: name.clone(),
+        attr_path: package.attr_path.clone(),
+        original_url: package.original_url.clone(),
+        installed_version: package.has_version().then(|| package.version.clone()),
+        available_version,
+        evaluated: evaluated.is_ok(),
+        held: holds.contains(name),
+      });
+    }
+
+    Ok(upgrades)
+  }
+}
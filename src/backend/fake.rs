@@ -0,0 +1,286 @@
+use std::{cell::RefCell, collections::{BTreeMap, BTreeSet}};
+
+use crate::{backend::{GenerationLink, NixBackend, ProfileLink}, error::Error};
+
+/// Scriptable stand-in for nix, used to exercise operations without a nix installation.
+///
+/// Read-only calls answer from the scripted state, and every call is recorded as an
+/// argv-like list (without the leading `nix`) so tests can assert on what would have run.
+#[derive(Default)]
+pub struct FakeBackend {
+  profile: String,
+  packages: Vec<(String, String)>,
+  manifest: Option<String>,
+  history: Vec<String>,
+  generations: Option<Vec<GenerationLink>>,
+  active: Option<usize>,
+  path_info: String,
+  closure_info: BTreeMap<String, String>,
+  remote_path_info: Option<String>,
+  search: String,
+  closures: String,
+  metadata: Option<String>,
+  evaluations: BTreeMap<String, String>,
+  failing: BTreeSet<&'static str>,
+  present: BTreeSet<String>,
+  corrupted: BTreeSet<String>,
+  calls: RefCell<Vec<Vec<String>>>,
+}
+
+impl FakeBackend {
+  pub fn new() -> Self {
+    FakeBackend {
+      profile: r#"{"elements":{},"version":3}"#.to_string(),
+      path_info: "{}".to_string(),
+      search: "{}".to_string(),
+      ..Default::default()
+    }
+  }
+
+  /// A version 3 manifest, the format `nix profile list --json` prints as well, holding `packages` from `flake:nixpkgs`.
+  /// The n-th package's store path hash is made of the digit n, e.g. `/nix/store/000…0-hello-2.12.1` for the first.
+  pub fn manifest<N: AsRef<str>, V: AsRef<str>>(packages: &[(N, V)]) -> String {
+    let elements: Vec<String> = packages.iter().enumerate().map(|(index, (name, version))| {
+      let (name, version) = (name.as_ref(), version.as_ref());
+      let suffix = if version.is_empty() { String::new() } else { format!("-{version}") };
+      let store_path = format!("/nix/store/{hash}-{name}{suffix}", hash = index.to_string().repeat(32));
+
+      format!(r#""{name}":{{"active":true,"attrPath":"legacyPackages.x86_64-linux.{name}","originalUrl":"flake:nixpkgs","outputs":null,"priority":5,"storePaths":["{store_path}"],"url":"github:NixOS/nixpkgs/0000000"}}"#)
+    }).collect();
+
+    format!(r#"{{"elements":{{{elements}}},"version":3}}"#, elements = elements.join(","))
+  }
+
+  pub fn with_profile(mut self, json: impl Into<String>) -> Self {
+    self.profile = json.into();
+    self
+  }
+
+  /// Installs `name` from `flake:nixpkgs` into the profile, see `manifest` for its store path.
+  pub fn with_package(mut self, name: &str, version: &str) -> Self {
+    self.packages.push((name.to_string(), version.to_string()));
+    self.profile = FakeBackend::manifest(&self.packages);
+    self
+  }
+
+  pub fn with_manifest(mut self, json: impl Into<String>) -> Self {
+    self.manifest = Some(json.into());
+    self
+  }
+
+  /// Adds the history output seen after one more profile change; the first is the initial state.
+  pub fn with_history(mut self, output: impl Into<String>) -> Self {
+    self.history.push(output.into());
+    self
+  }
+
+  /// Adds a generation link holding `manifest`; the last one added is the active generation.
+  pub fn with_generation(mut self, number: usize, created: u64, manifest: impl Into<String>) -> Self {
+    let generations = self.generations.get_or_insert_with(Vec::new);
+    generations.iter_mut().for_each(|generation| generation.active = false);
+    generations.push(GenerationLink {
+      number,
+      store_path: format!("/nix/store/{number:032}-profile"),
+      created,
+      active: true,
+      manifest: Some(manifest.into()),
+    });
+    self
+  }
+
+  /// Points the profile link at generation `number`, overriding the active generation picked by `with_generation`.
+  pub fn with_active(mut self, number: usize) -> Self {
+    self.active = Some(number);
+    self.generations.iter_mut().flatten().for_each(|generation| generation.active = generation.number == number);
+    self
+  }
+
+  pub fn with_path_info(mut self, json: impl Into<String>) -> Self {
+    self.path_info = json.into();
+    self
+  }
+
+  /// Answers a recursive `path-info` of `root` alone with `json` instead of the shared path info.
+  pub fn with_closure_info(mut self, root: impl Into<String>, json: impl Into<String>) -> Self {
+    self.closure_info.insert(root.into(), json.into());
+    self
+  }
+
+  pub fn with_remote_path_info(mut self, json: impl Into<String>) -> Self {
+    self.remote_path_info = Some(json.into());
+    self
+  }
+
+  pub fn with_search(mut self, json: impl Into<String>) -> Self {
+    self.search = json.into();
+    self
+  }
+
+  pub fn with_diff_closures(mut self, output: impl Into<String>) -> Self {
+    self.closures = output.into();
+    self
+  }
+
+  pub fn with_flake_metadata(mut self, json: impl Into<String>) -> Self {
+    self.metadata = Some(json.into());
+    self
+  }
+
+  pub fn with_eval(mut self, installable: impl Into<String>, output: impl Into<String>) -> Self {
+    self.evaluations.insert(installable.into(), output.into());
+    self
+  }
+
+  /// Marks `path` as present in the local store, which starts out empty.
+  pub fn with_present(mut self, path: impl Into<String>) -> Self {
+    self.present.insert(path.into());
+    self
+  }
+
+  /// Makes `nix store verify` report `path` as corrupted, also when it is only part of a verified closure.
+  pub fn with_corrupted(mut self, path: impl Into<String>) -> Self {
+    self.corrupted.insert(path.into());
+    self
+  }
+
+  /// Makes every call to `method` (e.g. `"add"`, `"remove"`) fail with exit code 1.
+  pub fn failing(mut self, method: &'static str) -> Self {
+    self.failing.insert(method);
+    self
+  }
+
+  pub fn calls(&self) -> Vec<Vec<String>> {
+    self.calls.borrow().clone()
+  }
+
+  fn record(&self, method: &'static str, call: Vec<String>) -> Result<(), Error> {
+    self.calls.borrow_mut().push(call);
+
+    if self.failing.contains(method) {
+      return Err(Error::CommandFailed { code: 1 });
+    }
+
+    Ok(())
+  }
+
+  fn call(prefix: &[&str], operands: &[String]) -> Vec<String> {
+    prefix.iter().map(|arg| arg.to_string()).chain(operands.iter().cloned()).collect()
+  }
+}
+
+impl NixBackend for FakeBackend {
+  fn profile_list(&self) -> Result<String, Error> {
+    self.record("list", Self::call(&["profile", "list"], &[]))?;
+    Ok(self.profile.clone())
+  }
+
+  fn profile_manifest(&self) -> Option<String> {
+    self.manifest.clone()
+  }
+
+  fn profile_add(&self, installables: &[String]) -> Result<(), Error> {
+    self.record("add", Self::call(&["profile", "add"], installables))
+  }
+
+  fn profile_remove(&self, elements: &[String]) -> Result<(), Error> {
+    self.record("remove", Self::call(&["profile", "remove"], elements))
+  }
+
+  fn profile_upgrade(&self, elements: &[String]) -> Result<(), Error> {
+    self.record("upgrade", Self::call(&["profile", "upgrade"], elements))
+  }
+
+  fn profile_history(&self) -> Result<String, Error> {
+    let changes = self.calls.borrow().iter().filter(|call| call[0] == "profile" && call[1] != "list" && call[1] != "history").count();
+    self.record("history", Self::call(&["profile", "history"], &[]))?;
+
+    let index = changes.min(self.history.len().saturating_sub(1));
+    Ok(self.history.get(index).cloned().unwrap_or_default())
+  }
+
+  fn profile_generations(&self) -> Option<Vec<GenerationLink>> {
+    self.generations.clone()
+  }
+
+  fn profile_link(&self) -> Option<ProfileLink> {
+    let active = self.active.or_else(|| self.generations.iter().flatten().find(|generation| generation.active).map(|generation| generation.number))?;
+    Some(ProfileLink { directory: "/nix/var/nix/profiles/per-user/nichts".into(), name: "profile".to_string(), active })
+  }
+
+  fn profile_rollback(&self, generation: usize) -> Result<(), Error> {
+    self.record("rollback", Self::call(&["profile", "rollback"], &[generation.to_string()]))
+  }
+
+  fn profile_wipe_history(&self, older_than: Option<&str>) -> Result<(), Error> {
+    let operands: Vec<String> = older_than.map(|age| vec![age.to_string()]).unwrap_or_default();
+    self.record("wipe-history", Self::call(&["profile", "wipe-history"], &operands))
+  }
+
+  fn eval(&self, installable: &str, _offline: bool) -> Result<String, Error> {
+    self.record("eval", Self::call(&["eval"], &[installable.to_string()]))?;
+    self.evaluations.get(installable).cloned().ok_or(Error::CommandFailed { code: 1 })
+  }
+
+  fn flake_metadata(&self, flake: &str) -> Result<String, Error> {
+    self.record("metadata", Self::call(&["flake", "metadata"], &[flake.to_string()]))?;
+    self.metadata.clone().ok_or(Error::CommandFailed { code: 1 })
+  }
+
+  fn current_system(&self) -> Result<String, Error> {
+    Ok("x86_64-linux".to_string())
+  }
+
+  fn eval_json(&self, installable: &str, _apply: &str) -> Result<String, Error> {
+    self.record("eval", Self::call(&["eval", "--json"], &[installable.to_string()]))?;
+    self.evaluations.get(installable).cloned().ok_or(Error::CommandFailed { code: 1 })
+  }
+
+  fn path_info(&self, paths: &[String], recursive: bool) -> Result<String, Error> {
+    self.record("path-info", Self::call(&["path-info"], paths))?;
+
+    if let [root] = paths
+      && recursive
+      && let Some(json) = self.closure_info.get(root) {
+      return Ok(json.clone());
+    }
+
+    Ok(self.path_info.clone())
+  }
+
+  fn remote_path_info(&self, installables: &[String]) -> Result<String, Error> {
+    self.record("remote-path-info", Self::call(&["path-info", "--store"], installables))?;
+    self.remote_path_info.clone().ok_or(Error::CommandFailed { code: 1 })
+  }
+
+  fn is_present(&self, store_path: &str) -> bool {
+    self.present.contains(store_path)
+  }
+
+  fn store_verify(&self, paths: &[String], recursive: bool) -> Result<(i32, String), Error> {
+    self.record("verify", Self::call(&["store", "verify"], paths))?;
+
+    // without a real store, every corrupted path counts as part of the verified closures
+    let corrupted: Vec<&String> = self.corrupted.iter().filter(|path| recursive || paths.contains(path)).collect();
+    let errors = corrupted.iter().map(|path| format!("path '{path}' was modified! expected hash 'sha256-A', got 'sha256-B'\n")).collect();
+
+    Ok((if corrupted.is_empty() { 0 } else { 1 }, errors))
+  }
+
+  fn diff_closures(&self, before: &str, after: &str) -> Result<String, Error> {
+    self.record("diff-closures", Self::call(&["store", "diff-closures"], &[before.to_string(), after.to_string()]))?;
+    Ok(self.closures.clone())
+  }
+
+  fn search(&self, flake: &str, patterns: &[String]) -> Result<String, Error> {
+    self.record("search", Self::call(&["search", flake], patterns))?;
+    Ok(self.search.clone())
+  }
+
+  fn collect_garbage(&self) -> Result<(), Error> {
+    self.record("gc", Self::call(&["store", "gc"], &[]))
+  }
+
+  fn version(&self) -> Result<String, Error> {
+    Ok("nix (Nix) 2.31.0\n".to_string())
+  }
+}
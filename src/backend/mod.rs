@@ -0,0 +1,50 @@
+use std::path::PathBuf;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone)]
+pub struct GenerationLink {
+  pub number: usize,
+  pub store_path: String,
+  pub created: u64,
+  pub active: bool,
+  pub manifest: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProfileLink {
+  pub directory: PathBuf,
+  pub name: String,
+  pub active: usize,
+}
+
+pub trait NixBackend {
+  fn profile_list(&self) -> Result<String, Error>;
+  fn profile_manifest(&self) -> Option<String>;
+  fn profile_add(&self, installables: &[String]) -> Result<(), Error>;
+  fn profile_remove(&self, elements: &[String]) -> Result<(), Error>;
+  /// Upgrades the given elements, or every element when `elements` is empty.
+  fn profile_upgrade(&self, elements: &[String]) -> Result<(), Error>;
+  fn profile_history(&self) -> Result<String, Error>;
+  fn profile_generations(&self) -> Option<Vec<GenerationLink>>;
+  fn profile_link(&self) -> Option<ProfileLink>;
+  fn profile_rollback(&self, generation: usize) -> Result<(), Error>;
+  fn profile_wipe_history(&self, older_than: Option<&str>) -> Result<(), Error>;
+  fn eval(&self, installable: &str, offline: bool) -> Result<String, Error>;
+  fn flake_metadata(&self, flake: &str) -> Result<String, Error>;
+  fn current_system(&self) -> Result<String, Error>;
+  fn eval_json(&self, installable: &str, apply: &str) -> Result<String, Error>;
+  fn path_info(&self, paths: &[String], recursive: bool) -> Result<String, Error>;
+  fn remote_path_info(&self, installables: &[String]) -> Result<String, Error>;
+  fn is_present(&self, store_path: &str) -> bool;
+  /// Exit status bits of `nix store verify` (1 for corrupted contents, 4 for invalid paths) and the errors it printed.
+  fn store_verify(&self, paths: &[String], recursive: bool) -> Result<(i32, String), Error>;
+  fn diff_closures(&self, before: &str, after: &str) -> Result<String, Error>;
+  fn search(&self, flake: &str, patterns: &[String]) -> Result<String, Error>;
+  fn collect_garbage(&self) -> Result<(), Error>;
+  fn version(&self) -> Result<String, Error>;
+}
+
+pub mod process;
+#[cfg(test)]
+pub mod fake;
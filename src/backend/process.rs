@@ -0,0 +1,233 @@
+use std::{fs::read_to_string, path::Path};
+
+use crate::{Cli, api::files::{profile_dir, profile_generations, profile_link}, backend::{GenerationLink, NixBackend, ProfileLink}, command::{catch_errors, catch_output, execute_command}, error::Error};
+
+static DEFAULT_SUBSTITUTER: &str = "https://cache.nixos.org";
+
+pub struct ProcessBackend {
+  impure: bool,
+  refresh: bool,
+  quiet: bool,
+  dry_run: bool,
+  no_color: bool,
+  profile: Option<String>,
+}
+
+impl ProcessBackend {
+  pub fn new(cli: &Cli) -> Self {
+    ProcessBackend {
+      impure: cli.impure(),
+      refresh: cli.refresh,
+      quiet: cli.quiet(),
+      dry_run: cli.print,
+      no_color: !cli.color(),
+      profile: cli.profile().map(String::from),
+    }
+  }
+
+  fn nix(args: &[&str]) -> Vec<String> {
+    std::iter::once("nix").chain(args.iter().copied()).map(String::from).collect()
+  }
+
+  fn push_profile(&self, argv: &mut Vec<String>) {
+    if let Some(profile) = &self.profile {
+      argv.push("--profile".to_string());
+      argv.push(profile.clone());
+    }
+  }
+
+  /// `nix profile <subcommand>` with the flags shared by every mutating profile command.
+  fn profile_command(&self, subcommand: &str) -> Vec<String> {
+    let mut argv = Self::nix(&["profile", subcommand]);
+
+    if self.impure {
+      argv.push("--impure".to_string());
+    }
+
+    if self.refresh {
+      argv.push("--refresh".to_string());
+    }
+
+    argv.push(if self.quiet { "--quiet" } else { "--verbose" }.to_string());
+    self.push_profile(&mut argv);
+
+    argv
+  }
+
+  fn execute(&self, argv: &[String]) -> Result<(), Error> {
+    execute_command(argv, self.no_color, self.dry_run)
+  }
+
+  fn with_operands(mut argv: Vec<String>, operands: &[String]) -> Vec<String> {
+    argv.push("--".to_string());
+    argv.extend(operands.iter().cloned());
+    argv
+  }
+}
+
+impl NixBackend for ProcessBackend {
+  fn profile_list(&self) -> Result<String, Error> {
+    let mut argv = Self::nix(&["profile", "list", "--json"]);
+    self.push_profile(&mut argv);
+
+    catch_output(&argv, self.no_color)
+  }
+
+  fn profile_manifest(&self) -> Option<String> {
+    // the profile links to its current generation, which links to the store path holding the manifest
+    let generation = profile_dir(self.profile.as_deref())?.canonicalize().ok()?;
+    read_to_string(generation.join("manifest.json")).ok()
+  }
+
+  fn profile_add(&self, installables: &[String]) -> Result<(), Error> {
+    self.execute(&Self::with_operands(self.profile_command("add"), installables))
+  }
+
+  fn profile_remove(&self, elements: &[String]) -> Result<(), Error> {
+    self.execute(&Self::with_operands(self.profile_command("remove"), elements))
+  }
+
+  fn profile_upgrade(&self, elements: &[String]) -> Result<(), Error> {
+    let mut argv = self.profile_command("upgrade");
+
+    if elements.is_empty() {
+      argv.push("--all".to_string());
+      return self.execute(&argv);
+    }
+
+    self.execute(&Self::with_operands(argv, elements))
+  }
+
+  fn profile_history(&self) -> Result<String, Error> {
+    let mut argv = Self::nix(&["profile", "history"]);
+    self.push_profile(&mut argv);
+
+    catch_output(&argv, true)
+  }
+
+  fn profile_generations(&self) -> Option<Vec<GenerationLink>> {
+    profile_generations(self.profile.as_deref())
+  }
+
+  fn profile_link(&self) -> Option<ProfileLink> {
+    profile_link(self.profile.as_deref())
+  }
+
+  fn profile_rollback(&self, generation: usize) -> Result<(), Error> {
+    let mut argv = self.profile_command("rollback");
+    argv.push("--to".to_string());
+    argv.push(generation.to_string());
+
+    self.execute(&argv)
+  }
+
+  fn profile_wipe_history(&self, older_than: Option<&str>) -> Result<(), Error> {
+    let mut argv = self.profile_command("wipe-history");
+
+    if let Some(older_than) = older_than {
+      argv.push("--older-than".to_string());
+      argv.push(older_than.to_string());
+    }
+
+    self.execute(&argv)
+  }
+
+  fn eval(&self, installable: &str, offline: bool) -> Result<String, Error> {
+    let mut argv = Self::nix(&["eval", "--raw"]);
+
+    if offline {
+      argv.push("--offline".to_string());
+    }
+
+    if self.refresh {
+      argv.push("--refresh".to_string());
+    }
+
+    catch_output(&Self::with_operands(argv, &[installable.to_string()]), self.no_color)
+  }
+
+  fn flake_metadata(&self, flake: &str) -> Result<String, Error> {
+    let mut argv = Self::nix(&["flake", "metadata", "--json"]);
+
+    if self.refresh {
+      argv.push("--refresh".to_string());
+    }
+
+    catch_output(&Self::with_operands(argv, &[flake.to_string()]), self.no_color)
+  }
+
+  fn current_system(&self) -> Result<String, Error> {
+    let system = catch_output(&Self::nix(&["eval", "--impure", "--raw", "--expr", "builtins.currentSystem"]), self.no_color)?;
+    Ok(system.trim().to_string())
+  }
+
+  fn eval_json(&self, installable: &str, apply: &str) -> Result<String, Error> {
+    let mut argv = Self::nix(&["eval", "--json", "--apply", apply]);
+
+    if self.impure {
+      argv.push("--impure".to_string());
+    }
+
+    if self.refresh {
+      argv.push("--refresh".to_string());
+    }
+
+    catch_output(&Self::with_operands(argv, &[installable.to_string()]), self.no_color)
+  }
+
+  fn path_info(&self, paths: &[String], recursive: bool) -> Result<String, Error> {
+    let mut argv = Self::nix(&["path-info", "--json", "--closure-size"]);
+
+    if recursive {
+      argv.push("--recursive".to_string());
+    }
+
+    catch_output(&Self::with_operands(argv, paths), self.no_color)
+  }
+
+  fn remote_path_info(&self, installables: &[String]) -> Result<String, Error> {
+    let substituters = catch_output(&Self::nix(&["config", "show", "substituters"]), self.no_color)?;
+    let substituter = substituters.split_whitespace().next().unwrap_or(DEFAULT_SUBSTITUTER).to_string();
+
+    // evaluate and instantiate locally, a binary cache cannot hold the flake sources or derivations
+    let mut argv = Self::nix(&["path-info", "--json", "--closure-size", "--recursive", "--eval-store", "auto", "--store"]);
+    argv.push(substituter);
+
+    if self.impure {
+      argv.push("--impure".to_string());
+    }
+
+    catch_output(&Self::with_operands(argv, installables), self.no_color)
+  }
+
+  fn is_present(&self, store_path: &str) -> bool {
+    Path::new(store_path).exists()
+  }
+
+  fn store_verify(&self, paths: &[String], recursive: bool) -> Result<(i32, String), Error> {
+    let mut argv = Self::nix(&["store", "verify", "--no-trust"]);
+
+    if recursive {
+      argv.push("--recursive".to_string());
+    }
+
+    catch_errors(&Self::with_operands(argv, paths), true)
+  }
+
+  fn diff_closures(&self, before: &str, after: &str) -> Result<String, Error> {
+    catch_output(&Self::with_operands(Self::nix(&["store", "diff-closures"]), &[before.to_string(), after.to_string()]), true)
+  }
+
+  fn search(&self, flake: &str, patterns: &[String]) -> Result<String, Error> {
+    let argv = Self::nix(&["search", "--json", flake]);
+    catch_output(&Self::with_operands(argv, patterns), self.no_color)
+  }
+
+  fn collect_garbage(&self) -> Result<(), Error> {
+    self.execute(&["nix-collect-garbage".to_string(), "--verbose".to_string()])
+  }
+
+  fn version(&self) -> Result<String, Error> {
+    catch_output(&Self::nix(&["--version"]), true)
+  }
+}
@@ -3,29 +3,7 @@ use std::io::{Write, stdin, stdout};
 
 use crate::error::Error;
 
-fn parse_args(command: impl Into<String>) -> Result<Vec<String>, Error> {
-  let command = command.into();
-  let command = command.trim();
-
-  if command.is_empty() {
-    return Err(Error::Unknown { code: 1, message: "Command was empty".to_string() });
-  }
-
-  let args = shlex::split(command);
-
-  if let None = args {
-    return Err(Error::Unknown { code: 1, message: "malformed command, cannot be parsed".to_string() })
-  }
-
-  let args = args.unwrap();
-  if args.is_empty() {
-    return Err(Error::Unknown { code: 1, message: "Command was empty".to_string() });
-  }
-
-  Ok(args)
-}
-
-pub fn create_command(argv: Vec<String>, no_color: bool) -> Result<Command, Error> {
+pub fn create_command(argv: &[String], no_color: bool) -> Result<Command, Error> {
   let mut args = argv.iter();
   let arg0 = args.next()
     .ok_or_else(|| Error::Unknown { code: 1, message: "Expected command binary".to_string() })?;
@@ -40,27 +18,66 @@ pub fn create_command(argv: Vec<String>, no_color: bool) -> Result<Command, Erro
   Ok(cmd)
 }
 
-pub fn execute_command(command: impl Into<String>, no_color: bool) -> Result<(), Error> {
-  let args = parse_args(command)?;
+fn quote_argv(argv: &[String]) -> String {
+  argv.iter().map(|arg| {
+    let is_plain = !arg.is_empty() && arg.chars().all(|c| c.is_ascii_alphanumeric() || "-_./:#+=@,%".contains(c));
+    if is_plain {
+      return arg.clone();
+    }
 
-  let exit_code = create_command(args, no_color)?
+    format!("'{}'", arg.replace('\'', "'\\''"))
+  }).collect::<Vec<String>>().join(" ")
+}
+
+pub fn execute_command(argv: &[String], no_color: bool, dry_run: bool) -> Result<(), Error> {
+  if dry_run {
+    println!("{}", quote_argv(argv));
+    return Ok(());
+  }
+
+  let exit_code = create_command(argv, no_color)?
     .stdin(Stdio::inherit())
-    .spawn().expect("Failed to execute command")
-    .wait().expect("Failed to wait for command");
+    .spawn()
+    .map_err(|e| Error::Unknown { code: 1, message: format!("failed to execute command: '{}'", e) })?
+    .wait()
+    .map_err(|e| Error::Unknown { code: 1, message: format!("failed to wait for command: '{}'", e) })?;
 
-  if let Some(code) = exit_code.code() {
-    if !code.eq(&0) {
-      return Err(Error::CommandFailed { code });
-    }
+  if let Some(code) = exit_code.code() && code != 0 {
+    return Err(Error::CommandFailed { code });
   }
 
   Ok(())
 }
 
-pub fn catch_output(command: impl Into<String>, no_color: bool) -> Result<String, Error> {
-  let args = parse_args(command)?;
+/// Like `execute_command`, but feeds `input` to the command's stdin.
+pub fn pipe_command(argv: &[String], input: &str, no_color: bool, dry_run: bool) -> Result<(), Error> {
+  if dry_run {
+    println!("{}", quote_argv(argv));
+    return Ok(());
+  }
+
+  let mut child = create_command(argv, no_color)?
+    .stdin(Stdio::piped())
+    .spawn()
+    .map_err(|e| Error::Unknown { code: 1, message: format!("failed to execute command: '{}'", e) })?;
+
+  if let Some(mut child_stdin) = child.stdin.take() {
+    // a command that does not read its input closes the pipe early, which is fine
+    let _ = child_stdin.write_all(input.as_bytes());
+  }
 
-  let output = create_command(args, no_color)?
+  let exit_code = child.wait()
+    .map_err(|e| Error::Unknown { code: 1, message: format!("failed to wait for command: '{}'", e) })?;
+
+  if let Some(code) = exit_code.code() && code != 0 {
+    return Err(Error::CommandFailed { code });
+  }
+
+  Ok(())
+}
+
+pub fn catch_output(argv: &[String], no_color: bool) -> Result<String, Error> {
+  let output = create_command(argv, no_color)?
     .output()
     .map_err(|e| Error::Unknown { code: 1, message: format!("failed to capture output: '{}'", e) })?;
 
@@ -72,6 +89,26 @@ pub fn catch_output(command: impl Into<String>, no_color: bool) -> Result<String
   Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
+/// Runs the command to completion and returns its exit status with what it printed to stderr.
+pub fn catch_errors(argv: &[String], no_color: bool) -> Result<(i32, String), Error> {
+  let output = create_command(argv, no_color)?
+    .output()
+    .map_err(|e| Error::Unknown { code: 1, message: format!("failed to capture output: '{}'", e) })?;
+
+  Ok((output.status.code().unwrap_or(-1), String::from_utf8_lossy(&output.stderr).to_string()))
+}
+
+pub fn prompt(message: impl Into<String>) -> String {
+  let message = message.into();
+  print!("{message} ");
+  stdout().flush().unwrap();
+
+  let mut input = String::new();
+  stdin().read_line(&mut input).unwrap();
+
+  input.trim().to_string()
+}
+
 pub fn confirm(message: impl Into<String>) -> bool {
   let message = message.into();
   print!("{message} [Y/n] ");
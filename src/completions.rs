@@ -0,0 +1,224 @@
+use crate::{
+  CLI_NAME, api::{history::HistoryVersions, query::PackageListing}, backend::NixBackend, error::Error,
+  operations::Operations, options::Options
+};
+
+/// Options that take a path as their value.
+static PATH_OPTIONS: &[Options] = &[Options::Flake, Options::Profile];
+
+fn quote(value: &str) -> String {
+  format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+/// The operations selected by an uppercase short flag, `-h` is handled as a plain option.
+fn operations() -> impl Iterator<Item = &'static Operations> {
+  Operations::all().iter().filter(|operation| !matches!(operation, Operations::Help))
+}
+
+fn operation_letters() -> String {
+  operations().map(Operations::short).collect()
+}
+
+/// Every `(short, long, description)` flag offered after `operation`, or without one for `None`.
+fn flags(operation: Option<&Operations>) -> Vec<(Option<char>, String, String)> {
+  let mut flags = Vec::new();
+
+  let Some(operation) = operation else {
+    for operation in Operations::all() {
+      flags.push((Some(operation.short()), operation.long().to_string(), operation.description().to_string()));
+    }
+
+    for option in Options::global() {
+      flags.push((Some(option.short()).filter(|short| *short != ' '), option.long().to_string(), option.description().to_string()));
+    }
+
+    return flags;
+  };
+
+  for option in Options::partial(operation) {
+    flags.push((Some(option.short()).filter(|short| *short != ' '), option.long().to_string(), option.description().to_string()));
+  }
+  flags.push((Some(Operations::Help.short()), Operations::Help.long().to_string(), Operations::Help.description().to_string()));
+
+  flags
+}
+
+fn words(operation: Option<&Operations>) -> String {
+  flags(operation).iter()
+    .flat_map(|(short, long, _)| short.map(|short| format!("-{short}")).into_iter().chain([format!("--{long}")]))
+    .collect::<Vec<String>>().join(" ")
+}
+
+fn bash() -> String {
+  let mut script = format!("# bash completion for {CLI_NAME}, generated by `{CLI_NAME} --generate-completions bash`\n\n");
+
+  script.push_str(&format!("_{CLI_NAME}() {{\n"));
+  script.push_str("  local cur=\"${COMP_WORDS[COMP_CWORD]}\" prev=\"${COMP_WORDS[COMP_CWORD-1]}\" operation=\"\" owns=\"\" rollback=\"\" flags=\"\" word\n\n");
+  script.push_str("  for word in \"${COMP_WORDS[@]:1:COMP_CWORD-1}\"; do\n    case \"$word\" in\n");
+  for operation in operations() {
+    script.push_str(&format!("      --{long}) operation={short} ;;\n", long = operation.long(), short = operation.short()));
+  }
+  script.push_str(&format!("      --{owns}) owns=1 ;;\n", owns = Options::Owns.long()));
+  script.push_str(&format!("      --{rollback}) rollback=1 ;;\n", rollback = Options::Rollback.long()));
+  script.push_str("      --*) ;;\n");
+  script.push_str(&format!("      -*)\n        [[ \"$word\" =~ ^-[^-]*([{letters}]) ]] && operation=\"${{BASH_REMATCH[1]}}\"\n", letters = operation_letters()));
+  script.push_str(&format!("        [[ \"$word\" =~ ^-[^-]*{owns} ]] && owns=1\n        ;;\n", owns = Options::Owns.short()));
+  script.push_str("    esac\n  done\n\n");
+
+  script.push_str("  case \"$operation\" in\n");
+  for operation in operations() {
+    script.push_str(&format!("    {short}) flags={words} ;;\n", short = operation.short(), words = quote(&words(Some(operation)))));
+  }
+  script.push_str(&format!("    *) flags={words} ;;\n  esac\n\n", words = quote(&words(None))));
+
+  let paths: Vec<String> = PATH_OPTIONS.iter().map(|option| format!("--{}", option.long())).collect();
+  script.push_str(&format!("  if [[ \"$prev\" == @({paths}) ]]; then\n", paths = paths.join("|")));
+  script.push_str("    compopt -o filenames 2>/dev/null\n    COMPREPLY=($(compgen -f -- \"$cur\"))\n    return\n  fi\n\n");
+
+  script.push_str("  if [[ \"$cur\" == -* ]]; then\n    COMPREPLY=($(compgen -W \"$flags\" -- \"$cur\"))\n    return\n  fi\n\n");
+
+  script.push_str(&format!("  if [[ \"$operation\" == {query} && -n \"$owns\" ]]; then\n", query = Operations::Query.short()));
+  script.push_str("    compopt -o filenames 2>/dev/null\n    COMPREPLY=($(compgen -f -- \"$cur\"))\n");
+  script.push_str(&format!("  elif [[ \"$operation\" == [{query}{remove}] ]]; then\n", query = Operations::Query.short(), remove = Operations::Remove.short()));
+  script.push_str(&format!("    COMPREPLY=($(compgen -W \"$({CLI_NAME} --complete packages 2>/dev/null)\" -- \"$cur\"))\n"));
+  script.push_str(&format!("  elif [[ \"$operation\" == {history} && -n \"$rollback\" ]]; then\n", history = Operations::History.short()));
+  script.push_str(&format!("    COMPREPLY=($(compgen -W \"$({CLI_NAME} --complete generations 2>/dev/null)\" -- \"$cur\"))\n"));
+  script.push_str("  fi\n}\n\n");
+
+  script.push_str(&format!("complete -F _{CLI_NAME} {CLI_NAME}\n"));
+  script
+}
+
+fn zsh() -> String {
+  let mut script = format!("#compdef {CLI_NAME}\n# zsh completion for {CLI_NAME}, generated by `{CLI_NAME} --generate-completions zsh`\n\n");
+
+  script.push_str(&format!("_{CLI_NAME}() {{\n"));
+  script.push_str("  local operation owns rollback word\n  local -a flags\n\n");
+  script.push_str("  for word in ${words[2,CURRENT-1]}; do\n    case $word in\n");
+  for operation in operations() {
+    script.push_str(&format!("      --{long}) operation={short} ;;\n", long = operation.long(), short = operation.short()));
+  }
+  script.push_str(&format!("      --{owns}) owns=1 ;;\n", owns = Options::Owns.long()));
+  script.push_str(&format!("      --{rollback}) rollback=1 ;;\n", rollback = Options::Rollback.long()));
+  script.push_str("      --*) ;;\n");
+  script.push_str(&format!("      -*)\n        [[ $word =~ '^-[^-]*([{letters}])' ]] && operation=$match[1]\n", letters = operation_letters()));
+  script.push_str(&format!("        [[ $word =~ '^-[^-]*{owns}' ]] && owns=1\n        ;;\n", owns = Options::Owns.short()));
+  script.push_str("    esac\n  done\n\n");
+
+  script.push_str("  case $operation in\n");
+  let describe = |operation: Option<&Operations>| {
+    flags(operation).iter()
+      .flat_map(|(short, long, description)| {
+        short.map(|short| format!("-{short}")).into_iter().chain([format!("--{long}")])
+          .map(move |flag| quote(&format!("{flag}:{description}")))
+      })
+      .collect::<Vec<String>>().join(" ")
+  };
+  for operation in operations() {
+    script.push_str(&format!("    {short}) flags=({describe}) ;;\n", short = operation.short(), describe = describe(Some(operation))));
+  }
+  script.push_str(&format!("    *) flags=({describe}) ;;\n  esac\n\n", describe = describe(None)));
+
+  let paths: Vec<String> = PATH_OPTIONS.iter().map(|option| format!("--{}", option.long())).collect();
+  script.push_str(&format!("  case ${{words[CURRENT-1]}} in\n    {paths}) _files; return ;;\n  esac\n\n", paths = paths.join("|")));
+
+  script.push_str("  if [[ $PREFIX == -* ]]; then\n    _describe -t options option flags\n    return\n  fi\n\n");
+
+  script.push_str(&format!("  if [[ $operation == {query} && -n $owns ]]; then\n    _files\n", query = Operations::Query.short()));
+  script.push_str(&format!("  elif [[ $operation == [{query}{remove}] ]]; then\n", query = Operations::Query.short(), remove = Operations::Remove.short()));
+  script.push_str(&format!("    compadd -- ${{(f)\"$({CLI_NAME} --complete packages 2>/dev/null)\"}}\n"));
+  script.push_str(&format!("  elif [[ $operation == {history} && -n $rollback ]]; then\n", history = Operations::History.short()));
+  script.push_str(&format!("    compadd -- ${{(f)\"$({CLI_NAME} --complete generations 2>/dev/null)\"}}\n"));
+  script.push_str("  fi\n}\n\n");
+
+  script.push_str(&format!("_{CLI_NAME} \"$@\"\n"));
+  script
+}
+
+fn fish() -> String {
+  let mut script = format!("# fish completion for {CLI_NAME}, generated by `{CLI_NAME} --generate-completions fish`\n\n");
+
+  script.push_str(&format!("function __{CLI_NAME}_operation\n  set -l operation ''\n  for word in (commandline -opc)[2..-1]\n    switch $word\n"));
+  for operation in operations() {
+    script.push_str(&format!("      case --{long}\n        set operation {short}\n", long = operation.long(), short = operation.short()));
+  }
+  script.push_str("      case '--*'\n      case '-*'\n");
+  script.push_str(&format!("        set -l letter (string match -r -g -- '^-[^-]*([{letters}])' $word)\n", letters = operation_letters()));
+  script.push_str("        test -n \"$letter\"; and set operation $letter\n    end\n  end\n\n  contains -- \"$operation\" $argv\nend\n\n");
+
+  script.push_str(&format!("complete -c {CLI_NAME} -f\n"));
+
+  let mut complete = |condition: String, operation: Option<&Operations>| {
+    for (short, long, description) in flags(operation) {
+      let short = short.map(|short| format!(" -s {short}")).unwrap_or_default();
+      let path = if PATH_OPTIONS.iter().any(|option| option.long() == long) { " -r -F" } else { "" };
+      script.push_str(&format!("complete -c {CLI_NAME} -n {condition}{short} -l {long}{path} -d {description}\n",
+        condition = quote(&condition), description = quote(&description)
+      ));
+    }
+  };
+
+  complete(format!("__{CLI_NAME}_operation \"\""), None);
+  for operation in operations() {
+    complete(format!("__{CLI_NAME}_operation {short}", short = operation.short()), Some(operation));
+  }
+
+  let owns = format!("__fish_contains_opt -s {short} {long}", short = Options::Owns.short(), long = Options::Owns.long());
+  script.push_str(&format!("\ncomplete -c {CLI_NAME} -n {condition} -F\n",
+    condition = quote(&format!("__{CLI_NAME}_operation {query}; and {owns}", query = Operations::Query.short()))
+  ));
+  script.push_str(&format!("complete -c {CLI_NAME} -n {condition} -a {values}\n",
+    condition = quote(&format!("__{CLI_NAME}_operation {query} {remove}; and not {owns}", query = Operations::Query.short(), remove = Operations::Remove.short())),
+    values = quote(&format!("({CLI_NAME} --complete packages 2>/dev/null)"))
+  ));
+  script.push_str(&format!("complete -c {CLI_NAME} -n {condition} -a {values}\n",
+    condition = quote(&format!("__{CLI_NAME}_operation {history}; and __fish_contains_opt {rollback}", history = Operations::History.short(), rollback = Options::Rollback.long())),
+    values = quote(&format!("({CLI_NAME} --complete generations 2>/dev/null)"))
+  ));
+
+  script
+}
+
+/// Prints the completion script for `shell`.
+pub fn generate(shell: &str) -> Result<(), Error> {
+  let script = match shell {
+    "bash" => bash(),
+    "zsh" => zsh(),
+    "fish" => fish(),
+    _ => return Err(Error::Unknown { code: 1, message: format!("unsupported shell '{shell}' (expected bash, zsh or fish)") }),
+  };
+
+  print!("{script}");
+  Ok(())
+}
+
+/// Prints the dynamic values the completion scripts ask for, one per line.
+pub fn values(nix: &dyn NixBackend, kind: &str) -> Result<(), Error> {
+  match kind {
+    "packages" => PackageListing::new(nix)?.keys().for_each(|name| println!("{name}")),
+    "generations" => HistoryVersions::new(nix)?.iter().for_each(|(generation, _)| println!("{generation}")),
+    _ => return Err(Error::Unknown { code: 1, message: format!("unknown completion '{kind}'") }),
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::{bash, fish, zsh};
+  use crate::{operations::Operations, options::Options};
+
+  #[test]
+  fn scripts_cover_every_operation_option() {
+    for script in [bash(), zsh(), fish()] {
+      for operation in Operations::all() {
+        for option in Options::partial(operation) {
+          assert!(script.contains(&format!("--{}", option.long())) || script.contains(&format!("-l {}", option.long())), "missing --{}", option.long());
+        }
+      }
+
+      assert!(script.contains("--complete packages"));
+      assert!(script.contains("--complete generations"));
+    }
+  }
+}
@@ -0,0 +1,158 @@
+use std::{env, fmt::Display, fs::read_to_string, io::ErrorKind, path::{Path, PathBuf}};
+
+use serde::Deserialize;
+
+use crate::{CLI_NAME, error::Error};
+
+pub static SYSTEM_CONFIG: &str = "/etc/nichts.conf";
+
+/// Resolves an XDG base directory, falling back to `$HOME/<fallback>` when the variable is unset.
+pub fn xdg_home(variable: &str, fallback: &str) -> Option<PathBuf> {
+  if let Some(path) = env::var_os(variable).filter(|path| !path.is_empty()) {
+    return Some(PathBuf::from(path));
+  }
+
+  env::var_os("HOME").map(|home| PathBuf::from(home).join(fallback))
+}
+
+#[derive(Debug, Default, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+  pub flake: Option<String>,
+  pub profile: Option<String>,
+  pub noconfirm: Option<bool>,
+  pub clean: Option<bool>,
+  pub impure: Option<bool>,
+  pub quiet: Option<bool>,
+  pub color: Option<bool>,
+  pub hold: Option<Vec<String>>,
+
+  #[serde(skip)]
+  pub sources: Vec<PathBuf>,
+  /// Where `hooks/` lives, left unset when no config directory can be determined.
+  #[serde(skip)]
+  pub config_dir: Option<PathBuf>,
+  /// Where the hold list and the transaction log live, left unset when no state directory can be determined.
+  #[serde(skip)]
+  pub state_dir: Option<PathBuf>,
+}
+
+impl Config {
+  pub fn user_path() -> Option<PathBuf> {
+    xdg_home("XDG_CONFIG_HOME", ".config").map(|path| path.join(CLI_NAME).join("config.toml"))
+  }
+
+  /// Loads the system file first and lets the user file override it.
+  pub fn load() -> Result<Self, Error> {
+    let mut config = Config::default();
+
+    let paths = std::iter::once(PathBuf::from(SYSTEM_CONFIG)).chain(Config::user_path());
+    for path in paths {
+      if let Some(file) = Config::read(&path)? {
+        config = file.merge(config);
+      }
+    }
+    config.config_dir = xdg_home("XDG_CONFIG_HOME", ".config").map(|path| path.join(CLI_NAME));
+    config.state_dir = xdg_home("XDG_STATE_HOME", ".local/state").map(|path| path.join(CLI_NAME));
+
+    Ok(config)
+  }
+
+  fn read(path: &Path) -> Result<Option<Self>, Error> {
+    let content = match read_to_string(path) {
+      Ok(content) => content,
+      Err(err) if err.kind() == ErrorKind::NotFound => return Ok(None),
+      Err(err) => return Err(Error::Unknown { code: 1, message: format!("failed to read '{}': {err}", path.display()) }),
+    };
+
+    let mut config = toml::from_str::<Config>(&content)
+      .map_err(|err| Error::Unknown { code: 1, message: format!("failed to parse '{}': {}", path.display(), err.message()) })?;
+    config.sources.push(path.to_path_buf());
+
+    Ok(Some(config))
+  }
+
+  /// Fills every unset value of `self` from `fallback`.
+  fn merge(self, fallback: Config) -> Self {
+    let mut sources = fallback.sources;
+    sources.extend(self.sources);
+
+    Config {
+      flake: self.flake.or(fallback.flake),
+      profile: self.profile.or(fallback.profile),
+      noconfirm: self.noconfirm.or(fallback.noconfirm),
+      clean: self.clean.or(fallback.clean),
+      impure: self.impure.or(fallback.impure),
+      quiet: self.quiet.or(fallback.quiet),
+      color: self.color.or(fallback.color),
+      hold: self.hold.or(fallback.hold),
+      config_dir: self.config_dir.or(fallback.config_dir),
+      state_dir: self.state_dir.or(fallback.state_dir),
+      sources,
+    }
+  }
+}
+
+impl Display for Config {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    let sources = self.sources.iter().map(|path| path.display().to_string()).collect::<Vec<String>>();
+    if sources.is_empty() {
+      writeln!(f, "# no configuration files loaded")?;
+    } else {
+      writeln!(f, "# loaded from {}", sources.join(", "))?;
+    }
+
+    if let Some(flake) = &self.flake {
+      writeln!(f, "flake = {flake:?}")?;
+    }
+
+    if let Some(profile) = &self.profile {
+      writeln!(f, "profile = {profile:?}")?;
+    }
+
+    writeln!(f, "noconfirm = {}", self.noconfirm.unwrap_or(false))?;
+    writeln!(f, "clean = {}", self.clean.unwrap_or(false))?;
+    writeln!(f, "impure = {}", self.impure.unwrap_or(false))?;
+    writeln!(f, "quiet = {}", self.quiet.unwrap_or(false))?;
+    writeln!(f, "color = {}", self.color.unwrap_or(true))?;
+    write!(f, "hold = {:?}", self.hold.clone().unwrap_or_default())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use super::Config;
+  use crate::Cli;
+
+  #[test]
+  fn user_file_overrides_system_file() {
+    let system = Config { clean: Some(true), quiet: Some(true), flake: Some("github:NixOS/nixpkgs".to_string()), ..Config::default() };
+    let user = Config { clean: Some(false), ..Config::default() };
+
+    let config = user.merge(system);
+
+    assert_eq!(config.clean, Some(false));
+    assert_eq!(config.quiet, Some(true));
+    assert_eq!(config.flake.as_deref(), Some("github:NixOS/nixpkgs"));
+  }
+
+  #[test]
+  fn flags_override_config() {
+    let config = Config { noconfirm: Some(true), clean: Some(true), quiet: Some(false), ..Config::default() };
+
+    let mut cli = Cli::try_parse_from(["nichts", "-Rq", "--noconfirm=false", "--clean=false", "hello"]).unwrap();
+    cli.config = config.clone();
+    assert!(!cli.noconfirm());
+    assert!(!cli.clean());
+    assert!(cli.quiet());
+    assert_eq!(cli.packages, ["hello"]);
+
+    let mut cli = Cli::try_parse_from(["nichts", "-R", "hello"]).unwrap();
+    cli.config = config;
+    assert!(cli.noconfirm());
+    assert!(cli.clean());
+    assert!(!cli.quiet());
+  }
+}
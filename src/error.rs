@@ -8,7 +8,9 @@ pub enum Error {
   NotSpecified { kind: String },
   CommandFailed { code: i32 },
   FailedRollback,
+  Interrupted,
   FailedJsonSerialization,
+  FailedEvaluation { installable: String },
   NoPackageFound,
 }
 
@@ -17,8 +19,10 @@ impl Display for Error {
     match self {
       Error::Unknown { code: _, message } => write!(f, "{message}"),
       Error::FailedRollback => write!(f, "failed to rollback versions"),
+      Error::Interrupted => write!(f, "interrupted"),
       Error::NoPackageFound => write!(f, "no package(s) found"),
       Error::FailedJsonSerialization => write!(f, "failed to serialize output"),
+      Error::FailedEvaluation { installable } => write!(f, "failed to evaluate '{installable}'"),
       Error::NotSpecified { kind } => write!(f, "no {kind} specified (use -h for help)"),
       Error::UnknownOption { option } => write!(f, "unrecognized option '{option}' (use -h for help)"),
       Error::InvalidOption { option, conflicts_with } => {
@@ -39,6 +43,7 @@ impl Error {
     match self {
       Error::CommandFailed { code } => *code,
       Error::Unknown { code, message: _ } => *code,
+      Error::Interrupted => 130,
       _ => 1,
     }
   }
@@ -0,0 +1,205 @@
+use std::{fmt::Display, fs::{read_dir, read_to_string}, path::{Path, PathBuf}, str::FromStr};
+
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{Cli, command::pipe_command, error::Error, operations::Operations};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookOperation {
+  Sync,
+  Remove,
+  Upgrade,
+  Rollback,
+  Wipe,
+}
+
+impl Display for HookOperation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      HookOperation::Sync => write!(f, "sync"),
+      HookOperation::Remove => write!(f, "remove"),
+      HookOperation::Upgrade => write!(f, "upgrade"),
+      HookOperation::Rollback => write!(f, "rollback"),
+      HookOperation::Wipe => write!(f, "wipe"),
+    }
+  }
+}
+
+impl FromStr for HookOperation {
+  type Err = Error;
+
+  fn from_str(value: &str) -> Result<Self, Self::Err> {
+    match value {
+      "sync" => Ok(HookOperation::Sync),
+      "remove" => Ok(HookOperation::Remove),
+      "upgrade" => Ok(HookOperation::Upgrade),
+      "rollback" => Ok(HookOperation::Rollback),
+      "wipe" => Ok(HookOperation::Wipe),
+      _ => Err(Error::Unknown { code: 1, message: format!("invalid operation '{value}' (expected sync, remove, upgrade, rollback or wipe)") }),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HookWhen {
+  Pre,
+  Post,
+}
+
+/// A single `hooks/*.toml` file, e.g.
+///
+/// ```toml
+/// description = "Rebuilding font cache"
+/// operations = ["sync", "remove", "upgrade"]
+/// packages = ["*font*"]
+/// when = "post"
+/// exec = ["fc-cache", "-f"]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Hook {
+  pub description: Option<String>,
+  pub operations: Vec<HookOperation>,
+  #[serde(default)]
+  pub packages: Vec<String>,
+  pub when: HookWhen,
+  pub exec: Vec<String>,
+  #[serde(default)]
+  pub abort_on_fail: bool,
+
+  #[serde(skip)]
+  pub name: String,
+}
+
+/// Turns a `*`/`?` glob into an anchored regex.
+fn glob_to_regex(glob: &str) -> Option<Regex> {
+  let pattern = regex::escape(glob).replace("\\*", ".*").replace("\\?", ".");
+  Regex::new(&format!("^{pattern}$")).ok()
+}
+
+impl Hook {
+  /// Returns the packages this hook triggers on, or `None` when it does not apply.
+  /// Hooks without `packages` trigger on every transaction of their operations.
+  pub fn matches(&self, operation: HookOperation, packages: &[String]) -> Option<Vec<String>> {
+    if !self.operations.contains(&operation) {
+      return None;
+    }
+
+    if self.packages.is_empty() {
+      return Some(packages.to_vec());
+    }
+
+    let globs: Vec<Regex> = self.packages.iter().filter_map(|glob| glob_to_regex(glob)).collect();
+    let matched: Vec<String> = packages.iter().filter(|package| globs.iter().any(|glob| glob.is_match(package))).cloned().collect();
+
+    if matched.is_empty() {
+      return None;
+    }
+
+    Some(matched)
+  }
+}
+
+#[derive(Debug, Default)]
+pub struct Hooks {
+  hooks: Vec<Hook>,
+}
+
+impl Hooks {
+  pub fn load(cli: &Cli) -> Self {
+    cli.config.config_dir.as_ref().map(|dir| Hooks::load_from(&dir.join("hooks"))).unwrap_or_default()
+  }
+
+  /// Reads every `*.toml` file in `dir` in file name order, skipping the ones that fail to parse.
+  pub fn load_from(dir: &Path) -> Self {
+    let Ok(entries) = read_dir(dir) else {
+      return Hooks::default();
+    };
+
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+      .filter(|path| path.extension().is_some_and(|extension| extension == "toml"))
+      .collect();
+    paths.sort();
+
+    let mut hooks = Vec::new();
+    for path in paths {
+      let parsed = read_to_string(&path).map_err(|err| err.to_string())
+        .and_then(|content| toml::from_str::<Hook>(&content).map_err(|err| err.message().to_string()));
+
+      match parsed {
+        Ok(mut hook) if !hook.exec.is_empty() => {
+          hook.name = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+          hooks.push(hook);
+        },
+        Ok(_) => Operations::show_warning(format!("hook '{}' has an empty 'exec', skipping", path.display())),
+        Err(err) => Operations::show_warning(format!("invalid hook '{}': {err}, skipping", path.display())),
+      }
+    }
+
+    Hooks { hooks }
+  }
+
+  /// Runs the matching hooks with their packages on stdin, one name per line.
+  /// Only failing `pre` hooks with `abort_on_fail` stop the transaction.
+  pub fn run(&self, cli: &Cli, when: HookWhen, operation: HookOperation, packages: &[String]) -> Result<(), Error> {
+    for hook in self.hooks.iter().filter(|hook| hook.when == when) {
+      let Some(matched) = hook.matches(operation, packages) else {
+        continue;
+      };
+
+      if !cli.quiet() {
+        println!(":: running {when} hook {name}...", when = if when == HookWhen::Pre { "pre-transaction" } else { "post-transaction" },
+          name = hook.description.as_deref().unwrap_or(&hook.name)
+        );
+      }
+
+      let mut input = matched.join("\n");
+      input.push('\n');
+
+      if let Err(err) = pipe_command(&hook.exec, &input, !cli.color(), cli.print) {
+        if when == HookWhen::Pre && hook.abort_on_fail {
+          return Err(Error::Unknown { code: 1, message: format!("hook '{}' failed, aborting transaction", hook.name) });
+        }
+
+        Operations::show_warning(format!("hook '{name}' failed: {err}", name = hook.name));
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use clap::Parser;
+
+  use super::{HookOperation, HookWhen, Hooks};
+  use crate::Cli;
+
+  #[test]
+  fn matches_operations_and_globs() {
+    let dir = std::env::temp_dir().join(format!("nichts-hooks-{}", std::process::id()));
+    fs::create_dir_all(dir.join("hooks")).unwrap();
+    fs::write(dir.join("hooks/10-fonts.toml"), "operations = [\"sync\", \"remove\"]\npackages = [\"*font*\"]\nwhen = \"post\"\nexec = [\"fc-cache\", \"-f\"]\n").unwrap();
+    fs::write(dir.join("hooks/20-broken.toml"), "operations = [\"sync\"]\n").unwrap();
+
+    let mut cli = Cli::try_parse_from(["nichts", "-S"]).unwrap();
+    cli.config.config_dir = Some(dir.clone());
+    let hooks = Hooks::load(&cli).hooks;
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(hooks.len(), 1);
+    assert_eq!(hooks[0].name, "10-fonts");
+    assert_eq!(hooks[0].when, HookWhen::Post);
+
+    let packages = vec!["hello".to_string(), "noto-fonts".to_string()];
+    assert_eq!(hooks[0].matches(HookOperation::Sync, &packages), Some(vec!["noto-fonts".to_string()]));
+    assert_eq!(hooks[0].matches(HookOperation::Upgrade, &packages), None);
+    assert_eq!(hooks[0].matches(HookOperation::Remove, &packages[..1]), None);
+  }
+}
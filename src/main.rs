@@ -1,9 +1,13 @@
+use std::path::PathBuf;
 use std::process::exit;
 
 use clap::Parser;
 use clap::error::ErrorKind;
 
+use crate::backend::process::ProcessBackend;
+use crate::config::Config;
 use crate::error::Error;
+use crate::operations::database::Database;
 use crate::operations::history::History;
 use crate::operations::{ Operation, Operations };
 use crate::operations::{
@@ -14,14 +18,20 @@ use crate::operations::{
   remove::Remove
 };
 
-use crate::options::{ Options};
+use crate::options::Options;
 use crate::options::clean::Clean;
 
 mod api;
+mod backend;
 mod operations;
 mod options;
 mod command;
+mod completions;
+mod config;
 mod error;
+mod hooks;
+mod manual;
+mod transaction;
 
 pub static CLI_NAME: &str = env!("CARGO_BIN_NAME");
 pub static CLI_VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -32,6 +42,9 @@ pub static DEFAULT_FLAKE: &str = "flake:nixpkgs";
 #[command(disable_help_flag = true)]
 #[command(disable_version_flag = true)]
 struct Cli {
+  #[arg(short = Operations::Database.short(), long = Operations::Database.long(), action = clap::ArgAction::SetTrue)]
+  database: bool,
+
   #[arg(short = Operations::Help.short(), long = Operations::Help.long(), action = clap::ArgAction::SetTrue)]
   help: bool,
 
@@ -50,14 +63,35 @@ struct Cli {
   #[arg(short = Operations::Version.short(), long = Operations::Version.long(), action = clap::ArgAction::SetTrue)]
   version: bool,
 
-  #[arg(short = Options::Clean.short(), long = Options::Clean.long(), action = clap::ArgAction::SetTrue)]
-  clean: bool,
+  #[arg(long = Options::Atomic.long(), action = clap::ArgAction::SetTrue)]
+  atomic: bool,
+
+  #[arg(long = Options::Bin.long(), action = clap::ArgAction::SetTrue)]
+  bin: bool,
+
+  #[arg(short = Options::Check.short(), long = Options::Check.long(), action = clap::ArgAction::Count)]
+  check: u8,
+
+  #[arg(short = Options::Clean.short(), long = Options::Clean.long(), num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+  clean: Option<bool>,
+
+  #[arg(long = Options::Closure.long(), action = clap::ArgAction::SetTrue)]
+  closure: bool,
+
+  #[arg(long = Options::Config.long(), action = clap::ArgAction::SetTrue)]
+  config_dump: bool,
 
   #[arg(long = Options::Flake.long(), num_args = 0..=1, default_value = None, default_missing_value = Some(""))]
   flake: Option<String>,
 
-  #[arg(long = Options::Impure.long(), action = clap::ArgAction::SetTrue)]
-  impure: bool,
+  #[arg(short = Options::Foreign.short(), long = Options::Foreign.long(), action = clap::ArgAction::SetTrue)]
+  foreign: bool,
+
+  #[arg(long = Options::Hold.long(), action = clap::ArgAction::SetTrue)]
+  hold: bool,
+
+  #[arg(long = Options::Impure.long(), num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+  impure: Option<bool>,
 
   #[arg(short = Options::Info.short(), long = Options::Info.long(), action = clap::ArgAction::SetTrue)]
   info: bool,
@@ -65,14 +99,29 @@ struct Cli {
   #[arg(long = Options::Json.long(), action = clap::ArgAction::SetTrue)]
   json: bool,
 
-  #[arg(long = Options::NoConfirm.long(), action = clap::ArgAction::SetTrue)]
-  noconfirm: bool,
+  #[arg(short = Options::List.short(), long = Options::List.long(), action = clap::ArgAction::SetTrue)]
+  list: bool,
+
+  #[arg(long = Options::Log.long(), action = clap::ArgAction::SetTrue)]
+  log: bool,
+
+  #[arg(long = Options::NoConfirm.long(), num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+  noconfirm: Option<bool>,
+
+  #[arg(long = Options::Operation.long())]
+  operation: Option<String>,
+
+  #[arg(short = Options::Owns.short(), long = Options::Owns.long(), action = clap::ArgAction::SetTrue)]
+  owns: bool,
+
+  #[arg(short = Options::Print.short(), long = Options::Print.long(), visible_alias = "dry-run", action = clap::ArgAction::SetTrue)]
+  print: bool,
 
   #[arg(long = Options::Profile.long(), num_args = 0..=1, default_value = None, default_missing_value = Some(""))]
   profile: Option<String>,
 
-  #[arg(short = Options::Quiet.short(), long = Options::Quiet.long(), action = clap::ArgAction::SetTrue)]
-  quiet: bool,
+  #[arg(short = Options::Quiet.short(), long = Options::Quiet.long(), num_args = 0..=1, require_equals = true, default_missing_value = "true")]
+  quiet: Option<bool>,
 
   #[arg(short = Options::Refresh.short(), long = Options::Refresh.long(), action = clap::ArgAction::SetTrue)]
   refresh: bool,
@@ -83,62 +132,90 @@ struct Cli {
   #[arg(short = Options::Search.short(), long = Options::Search.long(), action = clap::ArgAction::SetTrue)]
   search: bool,
 
+  #[arg(long = Options::Since.long())]
+  since: Option<String>,
+
+  #[arg(long = Options::Unhold.long(), action = clap::ArgAction::SetTrue)]
+  unhold: bool,
+
+  #[arg(long = Options::Until.long())]
+  until: Option<String>,
+
   #[arg(short = Options::Upgrade.short(), long = Options::Upgrade.long(), action = clap::ArgAction::SetTrue)]
   upgrade: bool,
 
   #[arg(long = Options::Wipe.long(), num_args = 0..=1, default_missing_value = Some(""))]
   wipe: Option<String>,
 
+  #[arg(long = "generate-completions", value_name = "shell", hide = true)]
+  generate_completions: Option<String>,
+
+  #[arg(long = "generate-man", value_name = "dir", hide = true)]
+  generate_man: Option<PathBuf>,
+
+  #[arg(long = "complete", value_name = "kind", hide = true)]
+  complete: Option<String>,
+
   packages: Vec<String>,
+
+  #[arg(skip)]
+  config: Config,
 }
 
 impl Cli {
-  fn flake_url(&self) -> &str {
-    if let Some(flake) = self.flake.as_deref() {
-      return &flake;
-    }
+  fn default_flake(&self) -> &str {
+    self.config.flake.as_deref().unwrap_or(DEFAULT_FLAKE)
+  }
 
-    return DEFAULT_FLAKE;
+  fn flake_url(&self) -> &str {
+    self.flake.as_deref().unwrap_or(self.default_flake())
   }
 
-  fn profile(&self) -> String {
-    if let Some(profile) = self.profile.as_deref() {
-      if !profile.is_empty() {
-        return format!(" --profile {profile}");
-      }
-    }
+  fn profile(&self) -> Option<&str> {
+    self.profile.as_deref().or(self.config.profile.as_deref()).filter(|profile| !profile.is_empty())
+  }
 
-    String::new()
+  // `--clean=false` and friends override a config file that turns them on
+  fn noconfirm(&self) -> bool {
+    self.noconfirm.or(self.config.noconfirm).unwrap_or(false)
   }
 
-  fn prepare_command(&self, command: impl Into<String>) -> String {
-    let mut command = command.into();
+  fn clean(&self) -> bool {
+    self.clean.or(self.config.clean).unwrap_or(false)
+  }
 
-    if self.impure {
-      command.push_str(" --impure");
-    }
+  fn impure(&self) -> bool {
+    self.impure.or(self.config.impure).unwrap_or(false)
+  }
 
-    if self.refresh {
-      command.push_str(" --refresh");
-    }
+  fn quiet(&self) -> bool {
+    self.quiet.or(self.config.quiet).unwrap_or(false)
+  }
 
-    if self.quiet {
-      command.push_str(" --quiet");
-    } else {
-      command.push_str(" --verbose");
-    }
+  fn color(&self) -> bool {
+    self.config.color.unwrap_or(true)
+  }
 
-    let profile = self.profile();
-    if !profile.is_empty() {
-      command.push_str(&profile);
+  /// The configuration files merged with the flags given on the command line.
+  fn effective_config(&self) -> Config {
+    Config {
+      flake: Some(self.flake_url().to_string()),
+      profile: self.profile().map(String::from),
+      noconfirm: Some(self.noconfirm()),
+      clean: Some(self.clean()),
+      impure: Some(self.impure()),
+      quiet: Some(self.quiet()),
+      color: Some(self.color()),
+      hold: self.config.hold.clone(),
+      sources: self.config.sources.clone(),
+      config_dir: self.config.config_dir.clone(),
+      state_dir: self.config.state_dir.clone(),
     }
-
-    command
   }
 }
 
 fn main() {
-  let cli = match Cli::try_parse() {
+  let mut cli = match Cli::try_parse() {
     Ok(cli) => cli,
     Err(err) => {
       match err.kind() {
@@ -158,18 +235,37 @@ fn main() {
     }
   };
 
-  let command_count = [cli.query, cli.remove, cli.sync, cli.version, cli.history]
+  match Config::load() {
+    Ok(config) => cli.config = config,
+    Err(err) => Operations::throw_if_needed(Err(err)),
+  }
+
+  let nix = ProcessBackend::new(&cli);
+
+  let command_count = [cli.database, cli.query, cli.remove, cli.sync, cli.version, cli.history]
     .iter()
     .filter(|&&x| x)
     .count();
 
   if command_count == 0 {
+    if let Some(shell) = &cli.generate_completions {
+      Operations::throw_if_needed(completions::generate(shell));
+    }
+
+    if let Some(dir) = &cli.generate_man {
+      Operations::throw_if_needed(manual::generate(dir));
+    }
+
+    if let Some(kind) = &cli.complete {
+      Operations::throw_if_needed(completions::values(&nix, kind));
+    }
+
     if cli.help {
-      Operations::throw_if_needed(Help::operate(&cli));
+      Operations::throw_if_needed(Help::operate(&cli, &nix));
     }
 
-    if cli.clean {
-      Operations::throw_if_needed(Clean::operate(&cli));
+    if cli.clean() {
+      Operations::throw_if_needed(Clean::operate(&cli, &nix));
     }
 
     Operations::throw_if_needed(Err(Error::NotSpecified { kind: "operation".to_string() }));
@@ -180,12 +276,13 @@ fn main() {
   }
 
   Operations::throw_if_needed(match () {
-    _ if cli.sync => Sync::operate(&cli),
-    _ if cli.query => Query::operate(&cli),
-    _ if cli.version => Version::operate(&cli),
-    _ if cli.remove => Remove::operate(&cli),
-    _ if cli.history => History::operate(&cli),
-    _ if cli.help => Help::operate(&cli),
+    _ if cli.sync => Sync::operate(&cli, &nix),
+    _ if cli.query => Query::operate(&cli, &nix),
+    _ if cli.version => Version::operate(&cli, &nix),
+    _ if cli.remove => Remove::operate(&cli, &nix),
+    _ if cli.history => History::operate(&cli, &nix),
+    _ if cli.database => Database::operate(&cli, &nix),
+    _ if cli.help => Help::operate(&cli, &nix),
     _ => Ok(())
   });
 }
@@ -0,0 +1,139 @@
+use std::{fs::{create_dir_all, write}, path::Path};
+
+use crate::{CLI_NAME, CLI_VERSION, error::Error, operations::Operations, options::Options};
+
+fn escape(text: &str) -> String {
+  let escaped = text.replace('\\', "\\\\").replace('-', "\\-");
+
+  if escaped.starts_with('.') || escaped.starts_with('\'') {
+    return format!("\\&{escaped}");
+  }
+
+  escaped
+}
+
+fn header(name: &str) -> String {
+  format!(".TH \"{title}\" 1 \"\" \"{CLI_NAME} {CLI_VERSION}\" \"User Commands\"\n", title = name.to_uppercase())
+}
+
+fn page_name(operation: &Operations) -> String {
+  format!("{CLI_NAME}-{long}", long = operation.long())
+}
+
+/// The operations that get a page of their own, `-h` has no options to document.
+fn operations() -> impl Iterator<Item = &'static Operations> {
+  Operations::all().iter().filter(|operation| !matches!(operation, Operations::Help))
+}
+
+fn option_entry(option: &Options) -> String {
+  let short = option.short();
+  let mut flags = format!("\\fB\\-\\-{long}\\fR", long = escape(option.long()));
+
+  if short != ' ' {
+    flags = format!("\\fB\\-{short}\\fR, {flags}");
+  }
+
+  if !option.arguments().is_empty() {
+    flags = format!("{flags} \\fI{arguments}\\fR", arguments = escape(option.arguments()));
+  }
+
+  format!(".TP\n{flags}\n{description}\n", description = escape(option.description()))
+}
+
+fn main_page() -> String {
+  let mut page = header(CLI_NAME);
+
+  page.push_str(&format!(".SH NAME\n{CLI_NAME} \\- nix profile wrapper with pacman\\-like syntax\n"));
+  page.push_str(&format!(".SH SYNOPSIS\n\\fB{CLI_NAME}\\fR \\fI<operation>\\fR [...]\n"));
+  page.push_str(&format!(".SH DESCRIPTION\n\\fB{CLI_NAME}\\fR manages a nix profile with the operations and flags known from pacman. \
+    Every operation maps to one or more \\fBnix profile\\fR commands, use \\fB\\-\\-print\\fR to see them without running anything.\n"));
+
+  page.push_str(".SH OPERATIONS\n");
+  for operation in Operations::all() {
+    page.push_str(&format!(".TP\n\\fB\\-{short}\\fR, \\fB\\-\\-{long}\\fR\n{description}",
+      short = operation.short(), long = escape(operation.long()), description = escape(operation.description())
+    ));
+
+    if matches!(operation, Operations::Help) {
+      page.push_str(", or the options of an operation when combined with it\n");
+    } else {
+      page.push_str(&format!(", see \\fB{name}\\fR(1)\n", name = escape(&page_name(operation))));
+    }
+  }
+
+  page.push_str(".SH OPTIONS\nThese options may also be used without an operation.\n");
+  for option in Options::global() {
+    page.push_str(&option_entry(option));
+  }
+
+  page.push_str(".SH FILES\n");
+  page.push_str(&format!(".TP\n/etc/{CLI_NAME}.conf, $XDG_CONFIG_HOME/{CLI_NAME}/config.toml\nsystem and user configuration, see \\fB{CLI_NAME} \\-V \\-\\-config\\fR\n"));
+  page.push_str(&format!(".TP\n$XDG_CONFIG_HOME/{CLI_NAME}/hooks/*.toml\ncommands to run before or after a transaction\n"));
+  page.push_str(&format!(".TP\n$XDG_STATE_HOME/{CLI_NAME}/hold\npackages held with \\fB{CLI_NAME} \\-D \\-\\-hold\\fR\n"));
+  page.push_str(&format!(".TP\n$XDG_STATE_HOME/{CLI_NAME}/{CLI_NAME}.log\ntransaction log, see \\fB{CLI_NAME} \\-H \\-\\-log\\fR\n"));
+  page.push_str(&format!(".TP\n$XDG_CACHE_HOME/{CLI_NAME}/index\nsearch index, one per flake, checked for a newer locked revision by \\fB{CLI_NAME} \\-Ssy\\fR\n"));
+
+  let see_also: Vec<String> = operations().map(|operation| format!("\\fB{name}\\fR(1)", name = escape(&page_name(operation)))).collect();
+  page.push_str(&format!(".SH SEE ALSO\n{see_also}, \\fBnix3\\-profile\\fR(1)\n", see_also = see_also.join(", ")));
+
+  page
+}
+
+fn operation_page(operation: &Operations) -> String {
+  let name = page_name(operation);
+  let mut page = header(&name);
+
+  page.push_str(&format!(".SH NAME\n{name} \\- {description}\n", name = escape(&name), description = escape(operation.description())));
+  page.push_str(&format!(".SH SYNOPSIS\n\\fB{CLI_NAME}\\fR {usage}\n", usage = escape(&operation.usage())));
+
+  let options = Options::partial(operation);
+  if !options.is_empty() {
+    page.push_str(".SH OPTIONS\n");
+    for option in options {
+      page.push_str(&option_entry(option));
+    }
+  }
+
+  page.push_str(&format!(".SH SEE ALSO\n\\fB{CLI_NAME}\\fR(1)\n"));
+  page
+}
+
+/// Writes `nichts.1` and one `nichts-<operation>.1` per operation into `dir`.
+pub fn generate(dir: &Path) -> Result<(), Error> {
+  let write_page = |name: &str, content: String| {
+    let path = dir.join(format!("{name}.1"));
+    write(&path, content).map_err(|err| Error::Unknown { code: 1, message: format!("failed to write '{}': {err}", path.display()) })
+  };
+
+  create_dir_all(dir).map_err(|err| Error::Unknown { code: 1, message: format!("failed to create '{}': {err}", dir.display()) })?;
+
+  write_page(CLI_NAME, main_page())?;
+  for operation in operations() {
+    write_page(&page_name(operation), operation_page(operation))?;
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use std::fs;
+
+  use super::generate;
+
+  #[test]
+  fn writes_a_page_per_operation() {
+    let dir = std::env::temp_dir().join(format!("nichts-man-{}", std::process::id()));
+    generate(&dir).unwrap();
+
+    let main = fs::read_to_string(dir.join("nichts.1")).unwrap();
+    let sync = fs::read_to_string(dir.join("nichts-sync.1")).unwrap();
+    let help_page = dir.join("nichts-help.1").exists();
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(main.contains("\\fBnichts\\-sync\\fR(1)"));
+    assert!(sync.contains("\\fB\\-y\\fR, \\fB\\-\\-refresh\\fR"));
+    assert!(sync.contains("{\\-S \\-\\-sync} [options] <package(s)>"));
+    assert!(!help_page);
+  }
+}
@@ -0,0 +1,80 @@
+use super::{Operation, Operations};
+use crate::{Cli, api::hold::HoldList, backend::NixBackend, error::Error, options::Options};
+
+pub struct Database;
+
+impl Operation for Database {
+  fn operate(cli: &Cli, _nix: &dyn NixBackend) -> Result<(), Error> {
+    Options::validate_options(cli, Operations::Database)?;
+
+    let mut holds = HoldList::new(cli);
+
+    if !cli.hold && !cli.unhold {
+      holds.iter().for_each(|name| println!("{name}"));
+      return Ok(());
+    }
+
+    if cli.packages.is_empty() {
+      return Err(Error::NotSpecified { kind: "target(s)".to_string() });
+    }
+
+    for package in &cli.packages {
+      if cli.hold {
+        if !holds.hold(package) {
+          Operations::show_warning(format!("{package} is already held"));
+        }
+        continue;
+      }
+
+      if holds.is_configured(package) {
+        Operations::show_warning(format!("{package} is held by the configuration file"));
+      } else if !holds.unhold(package) {
+        Operations::show_warning(format!("{package} is not held"));
+      }
+    }
+
+    holds.save()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use super::Database;
+  use crate::{Cli, Operation, api::hold::HoldList, backend::fake::FakeBackend};
+
+  #[test]
+  fn holds_persist_in_the_state_dir() {
+    let dir = std::env::temp_dir().join(format!("nichts-hold-{}", std::process::id()));
+    let parse = |args: &[&str]| {
+      let mut cli = Cli::try_parse_from(args).unwrap();
+      cli.config.state_dir = Some(dir.clone());
+      cli.config.hold = Some(vec!["cowsay".to_string()]);
+      cli
+    };
+
+    Database::operate(&parse(&["nichts", "-D", "--hold", "hello", "firefox"]), &FakeBackend::new()).unwrap();
+    Database::operate(&parse(&["nichts", "-D", "--unhold", "firefox", "cowsay"]), &FakeBackend::new()).unwrap();
+
+    let holds = HoldList::new(&parse(&["nichts", "-D"]));
+    assert_eq!(holds.iter().collect::<Vec<_>>(), ["cowsay", "hello"]);
+    assert_eq!(std::fs::read_to_string(dir.join("hold")).unwrap(), "hello\n");
+
+    std::fs::remove_dir_all(&dir).unwrap();
+  }
+
+  #[test]
+  fn saving_without_a_state_dir_fails() {
+    let cli = Cli::try_parse_from(["nichts", "-D", "--hold", "hello"]).unwrap();
+
+    assert!(Database::operate(&cli, &FakeBackend::new()).is_err());
+  }
+
+  #[test]
+  fn accepts_print() {
+    let cli = Cli::try_parse_from(["nichts", "-D", "--print"]).unwrap();
+
+    assert!(Database::operate(&cli, &FakeBackend::new()).is_ok());
+  }
+}
@@ -1,16 +1,18 @@
 use super::{Operation, Operations};
-use crate::{CLI_NAME, Cli, error::Error, options::Options};
+use crate::{CLI_NAME, Cli, backend::NixBackend, error::Error, options::Options};
 
 pub struct Help;
 
 impl Operation for Help {
-  fn operate(_cli: &Cli) -> Result<(), Error> {
+  fn operate(_cli: &Cli, _nix: &dyn NixBackend) -> Result<(), Error> {
     println!("usage:  {CLI_NAME} <operation> [...]");
     println!("operations:");
     Operations::print_help();
-    println!("");
+    println!();
     println!("options without operation:");
-    println!("  {}", Options::Clean);
+    for option in Options::global() {
+      println!("  {option}");
+    }
     println!("\nuse '{CLI_NAME} {}' with an operation for available options", Operations::Help.usage());
 
     Ok(())
@@ -1,37 +1,191 @@
 use super::{Operation, Operations};
-use crate::{Cli, api::history::{HistoryVersions, UNSET_VERSION}, command::{confirm, execute_command}, error::Error, options::{Options, clean::Clean}};
+use regex::Regex;
+
+use crate::{
+  Cli, api::{closure::ClosureDiff, history::{HistoryVersion, HistoryVersions, UNSET_VERSION}, log::{LogEntry, TransactionLog}}, backend::NixBackend, command::confirm,
+  error::Error, hooks::HookOperation, options::{Options, clean::Clean}, transaction::logged
+};
 
 pub struct History;
 
-impl Operation for History {
-  fn operate(cli: &Cli) -> Result<(), Error> {
-    Options::validate_options(&cli, Operations::History)?;
+impl History {
+  fn print_version(number: usize, version: &HistoryVersion) {
+    match &version.date {
+      Some(date) => println!("Version {number} ({date}):"),
+      None => println!("Version {number}:"),
+    }
 
-    if cli.wipe.is_some() {
-      if !cli.noconfirm && !confirm("Do you want to wipe your history?") {
-        return Err(Error::Unknown { code: 1, message: String::new() });
+    if let Some(store_path) = &version.store_path {
+      println!("  {store_path}");
+    }
+
+    if version.changes.is_empty() {
+      println!("  No changes.");
+    }
+
+    for change in &version.changes {
+      println!("  {name}: {previous} -> {latest}", name = change.name,
+        latest = change.current_version.as_deref().unwrap_or(UNSET_VERSION), previous = change.previous_version.as_deref().unwrap_or(UNSET_VERSION)
+      );
+    }
+  }
+
+  /// Keeps the log entries matching the `--since`, `--until`, `--operation` and package filters.
+  fn filter_log(cli: &Cli, entries: Vec<LogEntry>) -> Result<Vec<LogEntry>, Error> {
+    let date = Regex::new(r"^\d{4}(-\d{2}(-\d{2})?)?$").unwrap();
+    for (option, value) in [(Options::Since, &cli.since), (Options::Until, &cli.until)] {
+      if let Some(value) = value && !date.is_match(value) {
+        return Err(Error::Unknown { code: 1, message: format!("invalid date '{value}' for '--{}' (expected YYYY-MM-DD)", option.long()) });
       }
+    }
+
+    let operation = cli.operation.as_deref().map(str::parse::<HookOperation>).transpose()?;
+
+    Ok(entries.into_iter()
+      .filter(|entry| entry.within(cli.since.as_deref(), cli.until.as_deref()))
+      .filter(|entry| operation.is_none_or(|operation| entry.operation == operation))
+      .filter(|entry| cli.packages.is_empty() || cli.packages.iter().any(|package| entry.touches(package)))
+      .collect())
+  }
+
+  /// Shows the net changes between two generations, `to` defaulting to the current one.
+  fn range(cli: &Cli, versions: &HistoryVersions, from: &str, to: &str) -> Result<(), Error> {
+    if cli.rollback {
+      return Err(Error::Unknown { code: 1, message: "cannot roll back to a range of generations".to_string() });
+    }
+
+    let parse = |generation: &str| generation.parse::<usize>()
+      .map_err(|_| Error::Unknown { code: 1, message: "invalid version number".to_string() });
+
+    let from = parse(from)?;
+    let to = match to {
+      "" => versions.current().ok_or(Error::Unknown { code: 1, message: "no current generation".to_string() })?,
+      to => parse(to)?,
+    };
+
+    let diff = versions.diff(from, to)?;
+
+    if cli.json {
+      let Ok(serialized) = serde_json::to_string_pretty(&diff) else {
+        return Err(Error::FailedJsonSerialization);
+      };
+
+      println!("{serialized}");
+      return Ok(());
+    }
+
+    if diff.changes.is_empty() {
+      println!("No changes.");
+    }
 
-      let wipe_time = cli.wipe.clone().unwrap();
+    for change in &diff.changes {
+      println!("{name} {previous} -> {latest}", name = change.name,
+        latest = change.current_version.as_deref().unwrap_or(UNSET_VERSION), previous = change.previous_version.as_deref().unwrap_or(UNSET_VERSION)
+      );
+    }
+
+    Ok(())
+  }
+
+  /// Shows what changed in the store between two generations, `to` defaulting to the current one.
+  fn closure(cli: &Cli, nix: &dyn NixBackend, versions: &HistoryVersions) -> Result<(), Error> {
+    let parse = |generation: &String| generation.parse::<usize>()
+      .map_err(|_| Error::Unknown { code: 1, message: "invalid version number".to_string() });
+
+    let from = cli.packages.first().ok_or(Error::NotSpecified { kind: "generation".to_string() }).and_then(parse)?;
+    let to = match cli.packages.get(1) {
+      Some(to) => parse(to)?,
+      None => versions.current().ok_or(Error::Unknown { code: 1, message: "no current generation".to_string() })?,
+    };
+
+    let diff = ClosureDiff::new(nix, versions, from, to)?;
+
+    if cli.json {
+      let Ok(serialized) = serde_json::to_string_pretty(&diff) else {
+        return Err(Error::FailedJsonSerialization);
+      };
+
+      println!("{serialized}");
+      return Ok(());
+    }
+
+    let list = |versions: &[String]| if versions.is_empty() { UNSET_VERSION.to_string() } else { versions.join(", ") };
+
+    if diff.changes.is_empty() {
+      println!("No changes.");
+    } else {
+      println!("Packages ({length})", length = diff.changes.len());
+    }
 
-      let mut older_than = String::new();
-      if !wipe_time.is_empty() {
-        older_than = format!(" --older-than {wipe_time}");
+    for change in &diff.changes {
+      let size = change.size_delta.map(|size| format!(" ({})", Operations::format_size_delta(size))).unwrap_or_default();
+
+      if change.old_versions.is_empty() && change.new_versions.is_empty() {
+        println!("  {name}{size}", name = change.name);
+        continue;
       }
 
-      let command = cli.prepare_command("nix profile wipe-history");
-      execute_command(format!("{command}{older_than}"), false)?;
+      println!("  {name} {previous} -> {latest}{size}", name = change.name, previous = list(&change.old_versions), latest = list(&change.new_versions));
+    }
+    println!();
+
+    Operations::info_printer(&["Total Size Change"])("Total Size Change", &Operations::format_size_delta(diff.total_size_delta));
+
+    Ok(())
+  }
+
+  fn log(cli: &Cli) -> Result<(), Error> {
+    let entries = History::filter_log(cli, TransactionLog::read(cli)?)?;
+
+    if cli.json {
+      let Ok(serialized) = serde_json::to_string_pretty(&entries) else {
+        return Err(Error::FailedJsonSerialization);
+      };
 
+      println!("{serialized}");
       return Ok(());
     }
 
-    let versions = HistoryVersions::new(&cli);
+    for entry in &entries {
+      println!("{entry}");
+    }
+
+    Ok(())
+  }
+}
+
+impl Operation for History {
+  fn operate(cli: &Cli, nix: &dyn NixBackend) -> Result<(), Error> {
+    Options::validate_options(cli, Operations::History)?;
+
+    if cli.log {
+      return History::log(cli);
+    }
+
+    if let Some((option, _)) = [(Options::Since, cli.since.is_some()), (Options::Until, cli.until.is_some()), (Options::Operation, cli.operation.is_some())].iter().find(|(_, set)| *set) {
+      return Err(Error::Unknown { code: 1, message: format!("'--{}' may only be used with '--{}' (use -h for help)", option.long(), Options::Log.long()) });
+    }
+
+    if cli.wipe.is_some() {
+      if !cli.noconfirm() && !confirm("Do you want to wipe your history?") {
+        return Err(Error::Unknown { code: 1, message: String::new() });
+      }
+
+      let wipe_time = cli.wipe.as_deref().filter(|wipe_time| !wipe_time.is_empty());
+      return logged(cli, nix, HookOperation::Wipe, &[], || nix.profile_wipe_history(wipe_time));
+    }
+
+    let versions = HistoryVersions::new(nix)?;
+    if cli.closure {
+      return History::closure(cli, nix, &versions);
+    }
+
     if cli.packages.is_empty() {
       if cli.rollback {
         return Err(Error::NotSpecified { kind: "generation".to_string() });
       }
 
-      if cli.clean {
+      if cli.clean.is_some() {
         return Err(Error::Unknown { code: 1, message: "cannot use '--clean' in current state (use -h for help)".to_string() });
       }
 
@@ -44,71 +198,193 @@ impl Operation for History {
         return Err(Error::FailedJsonSerialization);
       }
 
-      for (version, changes) in versions.iter() {
-        println!("Version {version}:");
-        if changes.is_empty() {
-          println!("  No changes.");
-        } else {
-          for change in changes {
-            println!("  {flake}#{attribute}: {previous} -> {latest}",
-              flake = change.flake_url, attribute = change.flake_attribute,
-              latest = change.current_version.clone().unwrap_or(UNSET_VERSION.to_string()), previous = change.previous_version.clone().unwrap_or(UNSET_VERSION.to_string())
-            );
-          }
-        }
-        println!("");
+      for (number, version) in versions.iter() {
+        History::print_version(*number, version);
+        println!();
       }
 
       return Ok(());
     }
 
-    if let Some(arg0) = cli.packages.get(0) {
-      let arg0_unsigned = arg0.clone().parse::<usize>();
-      if let Err(_) = arg0_unsigned {
-        return Err(Error::Unknown { code: 1, message: "invalid version number".to_string() });
-      }
-      let arg0_unsigned = arg0_unsigned.unwrap();
+    if let Some((from, to)) = cli.packages.first().and_then(|arg0| arg0.split_once("..")) {
+      return History::range(cli, &versions, from, to);
+    }
+
+    if let Some(arg0) = cli.packages.first() {
+      let arg0_unsigned = arg0.parse::<usize>()
+        .map_err(|_| Error::Unknown { code: 1, message: "invalid version number".to_string() })?;
 
       if cli.rollback {
-        if !cli.noconfirm && !confirm("Do you want to rollback?") {
+        if !cli.noconfirm() && !confirm("Do you want to rollback?") {
           return Err(Error::Unknown { code: 1, message: String::new() });
         }
 
-        let command = cli.prepare_command("nix profile rollback");
-        execute_command(format!("{command} --to {arg0}"), false)?;
+        let packages = versions.changed_between(versions.current().unwrap_or(arg0_unsigned), arg0_unsigned);
+        logged(cli, nix, HookOperation::Rollback, &packages, || nix.profile_rollback(arg0_unsigned))?;
 
-        if cli.clean {
-          Clean::operate(&cli)?;
+        if cli.clean() {
+          Clean::operate(cli, nix)?;
         }
 
         return Err(Error::FailedRollback);
       }
 
       if cli.json {
-        if let Some(version_package) = versions.get(arg0_unsigned) {
-          if let Ok(serialized) = serde_json::to_string_pretty(&version_package) {
-            println!("{serialized}");
-            return Ok(());
-          }
+        if let Some(version_package) = versions.get(arg0_unsigned)
+          && let Ok(serialized) = serde_json::to_string_pretty(&version_package) {
+          println!("{serialized}");
+          return Ok(());
         }
 
         return Err(Error::FailedJsonSerialization);
       }
 
-      for (_, changes) in versions.iter().filter(|(version_number, _)| version_number.eq(&&arg0_unsigned)) {
-        if changes.is_empty() {
-          println!("No changes.");
-        } else {
-          for change in changes {
-            println!("{flake}#{attribute}: {previous} -> {latest}",
-              flake = change.flake_url, attribute = change.flake_attribute,
-              latest = change.current_version.clone().unwrap_or(UNSET_VERSION.to_string()), previous = change.previous_version.clone().unwrap_or(UNSET_VERSION.to_string())
-            );
-          }
-        }
+      if let Some(version) = versions.get(arg0_unsigned) {
+        History::print_version(arg0_unsigned, version);
       }
     }
 
-    return Ok(());
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use super::History;
+  use crate::{
+    Cli, Operation, api::{closure::ClosureDiff, history::{HistoryActions, HistoryVersions}, log::{TransactionLog, format_timestamp}},
+    backend::fake::FakeBackend, error::Error
+  };
+
+  static HISTORY: &str = "Version 1 (2025-01-01):\n  flake:nixpkgs#hello: ∅ -> 2.12.1\n\nVersion 2 (2025-01-02) <- 1:\n  flake:nixpkgs#hello: 2.12.1 -> 2.12.2\n";
+
+  #[test]
+  fn parses_history_versions() {
+    let versions = HistoryVersions::new(&FakeBackend::new().with_history(HISTORY)).unwrap();
+
+    assert_eq!(versions.get(1).unwrap().changes[0].action, HistoryActions::Added);
+    assert_eq!(versions.get(2).unwrap().changes[0].action, HistoryActions::Upgraded);
+    assert_eq!(versions.get(2).unwrap().changes[0].previous_version.as_deref(), Some("2.12.1"));
+    assert_eq!(versions.get(2).unwrap().date.as_deref(), Some("2025-01-02T00:00:00Z"));
+    assert!(versions.get(0).is_none());
+    assert_eq!(versions.current(), Some(2));
+
+    let rolled_back = HistoryVersions::new(&FakeBackend::new().with_history(HISTORY).with_active(1)).unwrap();
+    assert_eq!(rolled_back.current(), Some(1));
+
+    let unreadable = r#"{"version":1,"elements":[]}"#;
+    let linked = FakeBackend::new().with_generation(1, 1735725600, unreadable).with_generation(2, 1735812000, unreadable).with_history(HISTORY);
+    assert_eq!(HistoryVersions::new(&linked).unwrap().get(2).unwrap().date.as_deref(), Some("2025-01-02T10:00:00Z"));
+  }
+
+  #[test]
+  fn diffs_generation_manifests() {
+    let generations = || FakeBackend::new()
+      .with_generation(3, 1735725600, FakeBackend::manifest(&[("hello", "2.12.1"), ("cowsay", "3.8.4")]))
+      .with_generation(4, 1735812000, FakeBackend::manifest(&[("hello", "2.12.2")]))
+      .with_generation(5, 1735898400, FakeBackend::manifest(&[("hello", "2.12.2")]));
+    let nix = generations();
+    let versions = HistoryVersions::new(&nix).unwrap();
+
+    assert!(nix.calls().is_empty());
+    assert_eq!(versions.current(), Some(5));
+    assert_eq!(HistoryVersions::new(&generations().with_active(4)).unwrap().current(), Some(4));
+    assert_eq!(versions.get(3).unwrap().changes.len(), 2);
+    assert_eq!(versions.get(3).unwrap().date.as_deref(), Some("2025-01-01T10:00:00Z"));
+
+    let changes = &versions.get(4).unwrap().changes;
+    assert_eq!((changes[0].name.as_str(), &changes[0].action), ("cowsay", &HistoryActions::Removed));
+    assert_eq!((changes[1].name.as_str(), &changes[1].action), ("hello", &HistoryActions::Upgraded));
+    assert!(versions.get(5).unwrap().changes.is_empty());
+
+    let diff = versions.diff(3, 5).unwrap();
+    assert_eq!(diff.changes.iter().map(|change| (change.name.as_str(), &change.action)).collect::<Vec<_>>(), [("cowsay", &HistoryActions::Removed), ("hello", &HistoryActions::Upgraded)]);
+    assert_eq!(versions.diff(5, 3).unwrap().changes[0].action, HistoryActions::Added);
+    assert!(versions.diff(4, 5).unwrap().changes.is_empty());
+    assert!(versions.diff(4, 4).unwrap().changes.is_empty());
+    assert!(versions.diff(2, 5).is_err());
+  }
+
+  #[test]
+  fn diffs_generation_closures() {
+    let manifest = r#"{"version":3,"elements":{}}"#;
+    let nix = FakeBackend::new()
+      .with_generation(1, 0, manifest)
+      .with_generation(2, 0, manifest)
+      .with_diff_closures("firefox: 91.0 → 92.0, +2048.0 KiB\nbash: ∅ → 5.1\nglibc: -12.5 KiB\nzstd: 1.5.0, 1.5.2 → ∅, -1.0 MiB\n")
+      .with_closure_info("/nix/store/00000000000000000000000000000001-profile", r#"{"/nix/store/00000000000000000000000000000001-profile":{"narSize":1000,"references":[]},"/nix/store/11111111111111111111111111111111-zstd-1.5.2":{"narSize":1048576,"references":[]}}"#)
+      .with_closure_info("/nix/store/00000000000000000000000000000002-profile", r#"{"/nix/store/00000000000000000000000000000002-profile":{"narSize":1200,"references":[]},"/nix/store/22222222222222222222222222222222-firefox-92.0":{"narSize":2097152,"references":[]}}"#);
+
+    let cli = Cli::try_parse_from(["nichts", "-H", "--closure"]).unwrap();
+    assert!(matches!(History::operate(&cli, &nix), Err(Error::NotSpecified { .. })));
+
+    let cli = Cli::try_parse_from(["nichts", "-H", "--closure", "1"]).unwrap();
+    History::operate(&cli, &nix).unwrap();
+    assert!(nix.calls().contains(&["store", "diff-closures", "/nix/store/00000000000000000000000000000001-profile", "/nix/store/00000000000000000000000000000002-profile"].map(String::from).to_vec()));
+
+    let fallback = FakeBackend::new().with_history(HISTORY).with_active(2);
+    ClosureDiff::new(&fallback, &HistoryVersions::new(&fallback).unwrap(), 1, 2).unwrap();
+    assert!(fallback.calls().contains(&["store", "diff-closures", "/nix/var/nix/profiles/per-user/nichts/profile-1-link", "/nix/var/nix/profiles/per-user/nichts/profile-2-link"].map(String::from).to_vec()));
+
+    let diff = ClosureDiff::new(&nix, &HistoryVersions::new(&nix).unwrap(), 1, 2).unwrap();
+    assert_eq!(diff.changes.len(), 4);
+    assert_eq!(diff.changes[1].old_versions, Vec::<String>::new());
+    assert_eq!(diff.changes[1].size_delta, None);
+    assert_eq!(diff.changes[2].new_versions, Vec::<String>::new());
+    assert_eq!(diff.changes[3].old_versions, ["1.5.0", "1.5.2"]);
+    assert_eq!(diff.total_size_delta, 200 + 2048 * 1024 - 1024 * 1024);
+  }
+
+  #[test]
+  fn range_collapses_intermediate_churn() {
+    let history = "Version 1 (2025-01-01):\n  hello: ∅ -> 2.12.1\n\nVersion 2 (2025-01-02) <- 1:\n  cowsay: ∅ -> 3.8.4\n\nVersion 3 (2025-01-03) <- 2:\n  cowsay: 3.8.4 -> ∅\n  hello: 2.12.1 -> 2.12.2\n";
+    let versions = HistoryVersions::new(&FakeBackend::new().with_history(history)).unwrap();
+    let diff = versions.diff(1, 3).unwrap();
+
+    assert_eq!(diff.changes.len(), 1);
+    assert_eq!(diff.changes[0].name, "hello");
+    assert_eq!(diff.changes[0].previous_version.as_deref(), Some("2.12.1"));
+    assert_eq!(diff.changes[0].current_version.as_deref(), Some("2.12.2"));
+  }
+
+  #[test]
+  fn rolls_back_to_generation() {
+    let cli = Cli::try_parse_from(["nichts", "-H", "--rollback", "--noconfirm", "1"]).unwrap();
+    let nix = FakeBackend::new().with_history(HISTORY);
+
+    let _ = History::operate(&cli, &nix);
+
+    assert_eq!(nix.calls().last().unwrap(), &vec!["profile", "rollback", "1"]);
+
+    let cli = Cli::try_parse_from(["nichts", "-H", "--rollback", "--noconfirm", "2"]).unwrap();
+    let nix = FakeBackend::new().with_history(HISTORY);
+
+    let _ = History::operate(&cli, &nix);
+
+    assert_eq!(nix.calls().last().unwrap(), &vec!["profile", "rollback", "2"]);
+  }
+
+  #[test]
+  fn log_filters_by_package_date_and_operation() {
+    let content = [
+      r#"{"timestamp":"2025-01-01T10:00:00Z","operation":"sync","flags":["-S"],"packages":[{"name":"hello","oldVersion":null,"newVersion":"2.12.1"}],"generationBefore":1,"generationAfter":2,"status":0}"#,
+      r#"{"timestamp":"2025-02-01T10:00:00Z","operation":"upgrade","flags":["-Su"],"packages":[{"name":"hello","oldVersion":"2.12.1","newVersion":"2.12.2"}],"generationBefore":2,"generationAfter":3,"status":0}"#,
+      r#"{"timestamp":"2025-02-03T10:00:00Z","operation":"remove","flags":["-R"],"packages":[{"name":"cowsay","oldVersion":"3.8.4","newVersion":null}],"generationBefore":3,"generationAfter":4,"status":0}"#,
+      "not json",
+    ].join("\n");
+    let entries = TransactionLog::parse(&content);
+    assert_eq!(entries.len(), 3);
+
+    let cli = Cli::try_parse_from(["nichts", "-H", "--log", "--since", "2025-02", "hello"]).unwrap();
+    let filtered = History::filter_log(&cli, entries.clone()).unwrap();
+    assert_eq!(filtered.len(), 1);
+    assert_eq!(filtered[0].packages[0].to_string(), "upgraded hello (2.12.1 -> 2.12.2)");
+
+    let cli = Cli::try_parse_from(["nichts", "-H", "--log", "--until", "2025-02-01", "--operation", "remove"]).unwrap();
+    assert!(History::filter_log(&cli, entries).unwrap().is_empty());
+
+    assert_eq!(format_timestamp(1735725600), "2025-01-01T10:00:00Z");
   }
 }
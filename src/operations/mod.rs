@@ -1,8 +1,11 @@
 use std::{fmt::Display, process::exit};
 
-use crate::{ CLI_NAME, Cli, error::Error };
+use crate::{ CLI_NAME, Cli, backend::NixBackend, error::Error };
+
+static UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
 
 pub enum Operations {
+  Database,
   Sync,
   Remove,
   Query,
@@ -12,8 +15,9 @@ pub enum Operations {
 }
 
 impl Operations {
-  fn all() -> &'static [Operations] {
+  pub fn all() -> &'static [Operations] {
     &[
+      Operations::Database,
       Operations::Help,
       Operations::History,
       Operations::Query,
@@ -31,6 +35,7 @@ impl Operations {
 
   pub fn short(&self) -> char {
     match self {
+      Operations::Database => 'D',
       Operations::Sync => 'S',
       Operations::Remove => 'R',
       Operations::Query => 'Q',
@@ -42,6 +47,7 @@ impl Operations {
 
   pub fn long(&self) -> &str {
     match self {
+      Operations::Database => "database",
       Operations::Sync => "sync",
       Operations::Remove => "remove",
       Operations::Query => "query",
@@ -53,20 +59,59 @@ impl Operations {
 
   pub fn arguments(&self) -> &str {
     match self {
-      Operations::Sync | Operations::Query => "[options] <package(s)>",
+      Operations::Database | Operations::Sync | Operations::Query => "[options] <package(s)>",
       Operations::Remove => "<package(s)>",
-      Operations::History => "[options] [generation]",
+      Operations::History => "[options] [generation | from..[to]]",
       _ => ""
     }
   }
 
-  pub fn show_warning(message: impl Into<String>) -> () {
+  pub fn description(&self) -> &str {
+    match self {
+      Operations::Database => "manage the list of held packages",
+      Operations::Sync => "install, search and upgrade packages",
+      Operations::Remove => "remove packages from the profile",
+      Operations::Query => "query the installed packages",
+      Operations::Version => "display version information",
+      Operations::History => "view, roll back or wipe profile generations",
+      Operations::Help => "display help"
+    }
+  }
+
+  pub fn show_warning(message: impl Into<String>) {
     let message = message.into();
     if !message.is_empty() {
       eprintln!("warning: {message}");
     }
   }
 
+  pub fn format_size(bytes: u64) -> String {
+    let mut size = bytes as f64;
+    let mut unit_idx = 0;
+
+    while size >= 1024.0 && unit_idx < UNITS.len() - 1 {
+      size /= 1024.0;
+      unit_idx += 1;
+    }
+
+    format!("{:.2} {}", size, UNITS[unit_idx])
+  }
+
+  /// Like `format_size`, but keeps the sign of a size difference.
+  pub fn format_size_delta(bytes: i64) -> String {
+    let sign = if bytes < 0 { "-" } else { "" };
+    format!("{sign}{}", Operations::format_size(bytes.unsigned_abs()))
+  }
+
+  /// Prints `key : value` rows with keys padded to the longest of `keys`, as used by the `-i` views.
+  pub fn info_printer(keys: &[&str]) -> impl Fn(&str, &str) {
+    let padding = keys.iter().map(|key| key.len()).max().unwrap_or(0);
+
+    move |key: &str, value: &str| {
+      println!("{key}{} : {value}", " ".repeat(padding.saturating_sub(key.len())));
+    }
+  }
+
   pub fn throw_if_needed(res: Result<(), Error>) -> ! {
     if let Err(err) = res {
       let message = err.to_string();
@@ -86,7 +131,7 @@ impl Operations {
   fn max_len() -> usize {
     Operations::all()
       .iter().map(|operation| operation.len())
-      .max().unwrap_or_else(|| 0)
+      .max().unwrap_or(0)
   }
 
   pub fn usage(&self) -> String {
@@ -114,9 +159,10 @@ impl Display for Operations {
 }
 
 pub(super) trait Operation {
-  fn operate(cli: &Cli) -> Result<(), crate::error::Error>;
+  fn operate(cli: &Cli, nix: &dyn NixBackend) -> Result<(), crate::error::Error>;
 }
 
+pub mod database;
 pub mod help;
 pub mod history;
 pub mod remove;
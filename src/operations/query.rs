@@ -1,42 +1,222 @@
+use std::collections::BTreeMap;
+
 use crate::{
   Operation,
-  api::query::PackageListing,
+  api::{files::{Owner, find_target, profile_dir}, history::UNSET_VERSION, hold::HoldList, query::{Package, PackageListing}, store::Integrity, upgrades::PendingUpgrade},
+  backend::NixBackend,
   error::Error,
   operations::Operations,
   options::Options
 };
 
-static UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
-fn format_size(bytes: u64) -> String {
-  let mut size = bytes as f64;
-  let mut unit_idx = 0;
+pub struct Query;
+
+impl Query {
+  fn check(cli: &crate::Cli, nix: &dyn NixBackend, listing: &mut PackageListing) -> Result<(), Error> {
+    if !cli.packages.is_empty() {
+      listing.retain(|name, _| cli.packages.iter().any(|package| package == name));
+    }
+
+    let mut results = Vec::new();
+    for (name, package) in listing.to_vec() {
+      results.push(Integrity::check(nix, name, package, cli.check > 1)?);
+    }
+
+    if cli.json {
+      let Ok(serialized) = serde_json::to_string_pretty(&results) else {
+        return Err(Error::FailedJsonSerialization);
+      };
+
+      println!("{serialized}");
+    } else {
+      for result in &results {
+        for path in &result.missing {
+          Operations::show_warning(format!("{name}: {path} (No such file or directory)", name = result.name));
+        }
+
+        for path in &result.corrupted {
+          Operations::show_warning(format!("{name}: {path} (Contents do not match the NAR hash)", name = result.name));
+        }
+
+        for path in &result.invalid {
+          Operations::show_warning(format!("{name}: {path} (Not valid in the nix database)", name = result.name));
+        }
 
-  while size >= 1024.0 && unit_idx < UNITS.len() - 1 {
-    size /= 1024.0;
-    unit_idx += 1;
+        if !cli.quiet() || !result.is_ok() {
+          let scope = if result.closure { "closures" } else { "store paths" };
+          println!("{name}: {total} {scope} checked, {missing} missing, {corrupted} corrupted, {invalid} invalid",
+            name = result.name, total = result.store_paths, missing = result.missing.len(),
+            corrupted = result.corrupted.len(), invalid = result.invalid.len()
+          );
+        }
+      }
+    }
+
+    if results.iter().all(Integrity::is_ok) {
+      return Ok(());
+    }
+
+    Err(Error::Unknown { code: 1, message: String::new() })
   }
 
-  format!("{:.2} {}", size, UNITS[unit_idx])
-}
+  fn upgrades(cli: &crate::Cli, nix: &dyn NixBackend, listing: &mut PackageListing) -> Result<(), Error> {
+    if !cli.packages.is_empty() {
+      listing.retain(|name, _| cli.packages.iter().any(|package| package == name));
+    }
 
-pub struct Query;
+    let mut upgrades = PendingUpgrade::collect(nix, listing, &HoldList::new(cli))?;
+    upgrades.retain(|upgrade| upgrade.evaluated);
+
+    if cli.json {
+      let Ok(serialized) = serde_json::to_string_pretty(&upgrades) else {
+        return Err(Error::FailedJsonSerialization);
+      };
+
+      println!("{serialized}");
+    } else {
+      for upgrade in &upgrades {
+        if cli.quiet() {
+          println!("{name}", name = upgrade.name);
+          continue;
+        }
+
+        let held = if upgrade.held { " [held]" } else { "" };
+        println!("{name} {installed} -> {available}{held}", name = upgrade.name,
+          installed = upgrade.installed_version.as_deref().unwrap_or(UNSET_VERSION), available = upgrade.available_version.as_deref().unwrap_or(UNSET_VERSION)
+        );
+      }
+    }
+
+    if upgrades.iter().all(|upgrade| upgrade.held) {
+      return Ok(());
+    }
+
+    Err(Error::Unknown { code: 1, message: String::new() })
+  }
+
+  fn list(cli: &crate::Cli, listing: &mut PackageListing) -> Result<(), Error> {
+    if let Some(missing) = cli.packages.iter().find(|package| listing.get(package).is_none()) {
+      return Err(Error::Unknown { code: 1, message: format!("package '{missing}' was not found") });
+    }
+
+    if !cli.packages.is_empty() {
+      listing.retain(|name, _| cli.packages.iter().any(|package| package == name));
+    }
+
+    let profile = profile_dir(cli.profile()).unwrap_or_default();
+    let files: BTreeMap<&String, Vec<String>> = listing.to_vec().into_iter().map(|(name, package)| {
+      let files = package.files(cli.bin).into_iter().map(|file| profile.join(file).display().to_string()).collect();
+      (name, files)
+    }).collect();
+
+    if cli.json {
+      if let Ok(serialized) = serde_json::to_string_pretty(&files) {
+        println!("{serialized}");
+        return Ok(());
+      }
+
+      return Err(Error::FailedJsonSerialization);
+    }
+
+    for (name, files) in files {
+      for file in files {
+        if cli.quiet() {
+          println!("{file}");
+        } else {
+          println!("{name} {file}");
+        }
+      }
+    }
+
+    Ok(())
+  }
+
+  fn owns(cli: &crate::Cli, listing: &PackageListing) -> Result<(), Error> {
+    if cli.packages.is_empty() {
+      return Err(Error::NotSpecified { kind: "file(s)".to_string() });
+    }
+
+    let mut owners = Vec::new();
+    for target in &cli.packages {
+      let path = find_target(cli.profile(), target)
+        .ok_or_else(|| Error::Unknown { code: 1, message: format!("failed to find '{target}' in PATH or the profile") })?;
+
+      let owner = Owner::new(listing, &path)
+        .ok_or_else(|| Error::Unknown { code: 1, message: format!("no package owns {}", path.display()) })?;
+
+      owners.push(owner);
+    }
+
+    if cli.json {
+      if let Ok(serialized) = serde_json::to_string_pretty(&owners) {
+        println!("{serialized}");
+        return Ok(());
+      }
+
+      return Err(Error::FailedJsonSerialization);
+    }
+
+    for owner in owners {
+      if cli.quiet() {
+        println!("{name}", name = owner.name);
+        continue;
+      }
+
+      println!("{path} is owned by {name} {version} ({flake}#{attribute})",
+        path = owner.path, name = owner.name, version = owner.version,
+        flake = owner.original_url, attribute = owner.attr_path
+      );
+    }
+
+    Ok(())
+  }
+}
 
 impl Operation for Query {
-  fn operate(cli: &crate::Cli) -> Result<(), Error> {
-    Options::validate_options(&cli, Operations::Query)?;
+  fn operate(cli: &crate::Cli, nix: &dyn NixBackend) -> Result<(), Error> {
+    Options::validate_options(cli, Operations::Query)?;
 
-    let mut listing = PackageListing::new(&cli);
+    let mut listing = PackageListing::new(nix)?;
+
+    if cli.owns {
+      return Query::owns(cli, &listing);
+    }
 
     if cli.search {
       if cli.packages.is_empty() {
         return Err(Error::NotSpecified { kind: "pattern(s)".to_string() });
       }
 
-      listing.retain(|key, _| !cli.packages.iter().any(|keyword| key.contains(keyword)));
+      listing.retain(|key, _| cli.packages.iter().any(|keyword| key.contains(keyword)));
+    }
+
+    if cli.flake.is_some() {
+      let flake = cli.flake_url();
+      listing.retain(|_, package| package.is_from(if flake.is_empty() { cli.default_flake() } else { flake }));
+    }
+
+    if cli.foreign {
+      listing.retain(|_, package| !package.is_from(cli.default_flake()));
+    }
+
+    if cli.list {
+      return Query::list(cli, &mut listing);
+    }
+
+    if cli.upgrade {
+      return Query::upgrades(cli, nix, &mut listing);
+    }
+
+    if cli.check > 0 {
+      return Query::check(cli, nix, &mut listing);
     }
 
-    if let Some(flake) = &cli.flake {
-      listing.retain(|_, package| !package.original_url.eq(flake));
+    if cli.bin {
+      return Err(Error::Unknown { code: 1, message: "'--bin' may only be used with '--list' (use -h for help)".to_string() });
+    }
+
+    if cli.info || cli.json {
+      listing.load_sizes(nix)?;
     }
 
     if cli.json {
@@ -53,42 +233,217 @@ impl Operation for Query {
     }
 
     if cli.info {
-      listing.retain(|_, package| !package.active);
+      listing.retain(|_, package| package.active);
       if listing.is_empty() {
         return Err(Error::NoPackageFound);
       }
 
-      let padding = vec!["Name", "Flake attribute", "Flake Url", "Version", "Homepage", "Installed Size", "Store Paths"].iter().map(|str| str.len()).max().unwrap_or(0);
-
-      let print_info = |key: &str, value: &str| {
-        println!("{key}{} : {value}", " ".repeat(padding.saturating_sub(key.len())));
-      };
+      let print_info = Operations::info_printer(&["Name", "Flake attribute", "Flake Url", "Version", "Homepage", "Installed Size", "Closure Size", "Store Paths"]);
 
       for (name, package) in listing.to_vec() {
-        print_info("Name", &name);
+        print_info("Name", name);
         print_info("Flake attribute", &package.attr_path);
         print_info("Flake Url", &package.original_url);
         print_info("Version", &package.version);
 
         if let Some(homepage) = &package.homepage {
-          print_info("Homepage", &homepage);
+          print_info("Homepage", homepage);
         }
-        let installed_size = package.installed_size.unwrap_or(0);
-        print_info("Installed Size", &format_size(installed_size));
+        print_info("Installed Size", &Operations::format_size(package.installed_size.unwrap_or(0)));
+        print_info("Closure Size", &Operations::format_size(package.closure_size.unwrap_or(0)));
         print_info("Store Paths", &package.store_paths.join(" "));
-        println!("");
+        println!();
       }
 
       return Ok(());
     }
 
-    if cli.quiet {
+    if cli.quiet() {
       listing.keys().for_each(|key| println!("{key}"));
       return Ok(());
     }
 
-    listing.to_vec().iter().for_each(|(name, package)| println!("{name} {version}", version = package.version));
+    if cli.foreign {
+      let mut sources: BTreeMap<&String, Vec<(&String, &Package)>> = BTreeMap::new();
+      for (name, package) in listing.to_vec() {
+        sources.entry(&package.original_url).or_default().push((name, package));
+      }
+
+      for (original_url, packages) in sources {
+        println!("{original_url}");
+        for (name, package) in packages {
+          println!("  {name} {version} ({url})", version = package.version, url = package.url);
+        }
+        println!();
+      }
+
+      return Ok(());
+    }
+
+    let holds = HoldList::new(cli);
+    for (name, package) in listing.to_vec() {
+      let held = if holds.contains(name) { " [held]" } else { "" };
+      println!("{name} {version}{held}", version = package.version);
+    }
 
     Ok(())
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use std::{fs, os::unix::fs::symlink};
+
+  use super::Query;
+  use crate::{Cli, Operation, api::{files::{Owner, find_target}, query::PackageListing, store::Integrity}, backend::fake::FakeBackend, error::Error};
+
+  /// `hello` with a second, `man` output.
+  fn with_man_output() -> String {
+    FakeBackend::manifest(&[("hello", "2.12.1")]).replace(r#"-hello-2.12.1"]"#, r#"-hello-2.12.1","/nix/store/11111111111111111111111111111111-hello-2.12.1-man"]"#)
+  }
+
+  static PATH_INFO: &str = r#"{
+    "/nix/store/00000000000000000000000000000000-hello-2.12.1": {"narSize": 100, "closureSize": 1100, "references": ["/nix/store/22222222222222222222222222222222-glibc-2.40"]},
+    "/nix/store/11111111111111111111111111111111-hello-2.12.1-man": {"narSize": 10, "closureSize": 10, "references": []},
+    "/nix/store/22222222222222222222222222222222-glibc-2.40": {"narSize": 1000, "closureSize": 1000, "references": []}
+  }"#;
+
+  #[test]
+  fn empty_profile_has_no_packages() {
+    let cli = Cli::try_parse_from(["nichts", "-Q"]).unwrap();
+
+    assert!(matches!(Query::operate(&cli, &FakeBackend::new()), Err(Error::NoPackageFound)));
+  }
+
+  #[test]
+  fn reads_manifest_before_asking_nix() {
+    let manifest = r#"{"version":2,"elements":[
+      {"active":true,"attrPath":"legacyPackages.x86_64-linux.hello","originalUrl":"flake:nixpkgs","outputs":null,"priority":5,"storePaths":["/nix/store/00000000000000000000000000000000-hello-2.12.1"],"url":"github:NixOS/nixpkgs/0000000"},
+      {"active":true,"priority":5,"storePaths":["/nix/store/11111111111111111111111111111111-cowsay-3.8.4"]},
+      {"active":true,"attrPath":"packages.x86_64-linux.default","originalUrl":"github:NixOS/nix","outputs":null,"priority":5,"storePaths":["/nix/store/22222222222222222222222222222222-nix-2.24.0"],"url":"github:NixOS/nix/0000000"},
+      {"active":true,"attrPath":"packages.x86_64-linux.default","originalUrl":"github:edolstra/nix-warez?dir=blender","outputs":null,"priority":5,"storePaths":["/nix/store/33333333333333333333333333333333-blender-bin-4.2.0"],"url":"github:edolstra/nix-warez/0000000?dir=blender"},
+      {"active":true,"attrPath":"defaultPackage.x86_64-linux","originalUrl":"path:/home/user/project","outputs":null,"priority":5,"storePaths":["/nix/store/44444444444444444444444444444444-project-0.1.0"],"url":"path:/home/user/project"}
+    ]}"#;
+    let nix = FakeBackend::new().with_manifest(manifest);
+    let listing = PackageListing::new(&nix).unwrap();
+
+    assert_eq!(listing.keys().collect::<Vec<_>>(), ["blender", "cowsay", "hello", "nix", "project"]);
+    assert_eq!(listing.get("cowsay").unwrap().version, "3.8.4");
+    assert!(nix.calls().is_empty());
+
+    let nix = FakeBackend::new().with_package("hello", "2.12.1").with_manifest(r#"{"version":1,"elements":[]}"#);
+    assert!(PackageListing::new(&nix).unwrap().get("hello").is_some());
+    assert_eq!(nix.calls(), [["profile", "list"]]);
+  }
+
+  #[test]
+  fn upgrades_fail_only_when_outdated() {
+    let cli = Cli::try_parse_from(["nichts", "-Qu"]).unwrap();
+    let installable = "flake:nixpkgs#legacyPackages.x86_64-linux.hello";
+    let evaluated = |version: &str, out_path: &str| format!(r#"{{"version":"{version}","outPaths":["{out_path}"]}}"#);
+
+    let current = FakeBackend::new().with_package("hello", "2.12.1").with_eval(installable, evaluated("2.12.1", "/nix/store/00000000000000000000000000000000-hello-2.12.1"));
+    assert!(Query::operate(&cli, &current).is_ok());
+
+    let outdated = FakeBackend::new().with_package("hello", "2.12.1").with_eval(installable, evaluated("2.12.2", "/nix/store/22222222222222222222222222222222-hello-2.12.2"));
+    assert!(matches!(Query::operate(&cli, &outdated), Err(Error::Unknown { code: 1, .. })));
+
+    let unversioned = FakeBackend::new().with_package("hello", "").with_eval(installable, evaluated("2.12.2", "/nix/store/00000000000000000000000000000000-hello"));
+    assert!(Query::operate(&cli, &unversioned).is_ok());
+  }
+
+  #[test]
+  fn foreign_skips_default_flake() {
+    let cli = Cli::try_parse_from(["nichts", "-Qm"]).unwrap();
+    let nix = FakeBackend::new().with_package("hello", "2.12.1");
+
+    assert!(matches!(Query::operate(&cli, &nix), Err(Error::NoPackageFound)));
+
+    let nix = FakeBackend::new().with_profile(FakeBackend::manifest(&[("hello", "2.12.1")]).replace(r#""originalUrl":"flake:nixpkgs""#, r#""originalUrl":"github:me/pkgs""#));
+    assert!(Query::operate(&cli, &nix).is_ok());
+  }
+
+  #[test]
+  fn search_and_info_keep_matching_packages() {
+    let profile = FakeBackend::manifest(&[("hello", "2.12.1")]);
+    let query = |args: &[&str], profile: &str| Query::operate(&Cli::try_parse_from(args).unwrap(), &FakeBackend::new().with_profile(profile));
+
+    assert!(query(&["nichts", "-Qs", "hell"], &profile).is_ok());
+    assert!(matches!(query(&["nichts", "-Qs", "cowsay"], &profile), Err(Error::NoPackageFound)));
+
+    assert!(query(&["nichts", "-Qi"], &profile).is_ok());
+    assert!(matches!(query(&["nichts", "-Qi"], &profile.replace(r#""active":true"#, r#""active":false"#)), Err(Error::NoPackageFound)));
+  }
+
+  #[test]
+  fn check_reports_missing_paths() {
+    let cli = Cli::try_parse_from(["nichts", "-Qkk", "--json"]).unwrap();
+    let nix = FakeBackend::new().with_package("hello", "2.12.1");
+
+    assert!(matches!(Query::operate(&cli, &nix), Err(Error::Unknown { code: 1, .. })));
+    assert!(!nix.calls().iter().any(|call| call[0] == "store"));
+  }
+
+  #[test]
+  fn check_reports_corrupted_paths() {
+    let present = "/nix/store/00000000000000000000000000000000-hello-2.12.1";
+    let nix = FakeBackend::new().with_profile(with_man_output()).with_present(present).with_corrupted(present);
+
+    let listing = PackageListing::new(&nix).unwrap();
+    let integrity = Integrity::check(&nix, "hello", listing.get("hello").unwrap(), false).unwrap();
+
+    assert_eq!(integrity.corrupted, [present]);
+    assert_eq!(integrity.missing.len(), 1);
+
+    let dependency = "/nix/store/22222222222222222222222222222222-glibc-2.40";
+    let nix = FakeBackend::new().with_profile(with_man_output()).with_present(present).with_corrupted(dependency);
+
+    let listing = PackageListing::new(&nix).unwrap();
+    assert!(Integrity::check(&nix, "hello", listing.get("hello").unwrap(), false).unwrap().corrupted.is_empty());
+
+    let integrity = Integrity::check(&nix, "hello", listing.get("hello").unwrap(), true).unwrap();
+    assert_eq!(integrity.corrupted, [dependency]);
+    assert!(integrity.invalid.is_empty());
+  }
+
+  #[test]
+  fn sizes_count_shared_closure_once() {
+    let nix = FakeBackend::new().with_profile(with_man_output()).with_path_info(PATH_INFO);
+
+    let mut listing = PackageListing::new(&nix).unwrap();
+    assert_eq!(listing.get("hello").unwrap().installed_size, None);
+
+    listing.load_sizes(&nix).unwrap();
+    let package = listing.get("hello").unwrap();
+
+    assert_eq!(package.version, "2.12.1");
+    assert_eq!(package.installed_size, Some(110));
+    assert_eq!(package.closure_size, Some(1110));
+  }
+
+  #[test]
+  fn owner_and_files_follow_profile_symlinks() {
+    let root = std::env::temp_dir().join(format!("nichts-owns-{}", std::process::id()));
+    let store_path = root.join("store/00000000000000000000000000000000-hello-2.12.1");
+    fs::create_dir_all(store_path.join("bin")).unwrap();
+    fs::create_dir_all(root.join("profile")).unwrap();
+    fs::write(store_path.join("bin/hello"), "").unwrap();
+    symlink(store_path.join("bin"), root.join("profile/bin")).unwrap();
+
+    let profile = FakeBackend::manifest(&[("hello", "2.12.1")]).replace("/nix/store/00000000000000000000000000000000-hello-2.12.1", &store_path.display().to_string());
+    let listing = PackageListing::new(&FakeBackend::new().with_profile(profile)).unwrap();
+
+    let path = find_target(root.join("profile").to_str(), "hello").unwrap();
+    let owner = Owner::new(&listing, &path);
+    let files = listing.get("hello").unwrap().files(true);
+    fs::remove_dir_all(&root).unwrap();
+
+    assert_eq!(files, vec![std::path::PathBuf::from("bin/hello")]);
+
+    let owner = owner.unwrap();
+    assert_eq!(owner.name, "hello");
+    assert_eq!(owner.resolved, store_path.join("bin/hello").display().to_string());
+  }
+}
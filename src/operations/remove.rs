@@ -1,21 +1,21 @@
 use super::{Operation, Operations};
-use crate::{ api::query::PackageListing, command::{catch_output, confirm, execute_command}, error::Error, options::{Options, clean::Clean} };
+use crate::{ api::query::PackageListing, backend::NixBackend, command::confirm, error::Error, hooks::HookOperation, options::{Options, clean::Clean}, transaction::transaction };
 
 pub struct Remove;
 
 impl Operation for Remove {
-  fn operate(cli: &crate::Cli) -> Result<(), Error> {
-    Options::validate_options(&cli, Operations::Remove)?;
+  fn operate(cli: &crate::Cli, nix: &dyn NixBackend) -> Result<(), Error> {
+    Options::validate_options(cli, Operations::Remove)?;
 
     if cli.packages.is_empty() {
       return Err(Error::NotSpecified { kind: "target(s)".to_string() });
     }
 
-    let mut installed_packages = PackageListing::new(&cli);
-    let installed_packages_keys: Vec<String> = installed_packages.keys().map(|key| key.clone()).collect();
+    let installed_packages = PackageListing::new(nix)?;
+    let installed_packages_keys: Vec<String> = installed_packages.keys().cloned().collect();
 
     let packages = &cli.packages;
-    let not_installed_packages: Vec<String> = packages.iter().filter(|key| !installed_packages_keys.contains(key)).map(|key| key.clone()).collect();
+    let not_installed_packages: Vec<String> = packages.iter().filter(|key| !installed_packages_keys.contains(key)).cloned().collect();
 
     if !not_installed_packages.is_empty() {
       for package in not_installed_packages {
@@ -25,45 +25,63 @@ impl Operation for Remove {
     }
 
     let flake = cli.flake_url();
-    let new_packages: Vec<String> = packages.iter().map(|package| {
-      let mut package_format = package.clone();
+    let mut new_packages = Vec::new();
+    for name in packages {
+      let Some(installed_package) = installed_packages.get(name) else {
+        continue;
+      };
+
+      if cli.flake.is_some() && !installed_package.is_from(flake) {
+        return Err(Error::Unknown { code: 1, message: format!("flake '{flake}' does not provide attribute '{name}'")});
+      }
 
-      let _ = installed_packages.any_mut(|name, installed_package| {
-        if !name.eq(package.as_str()) {
-          return false;
-        }
+      let latest_version = nix.eval(&format!("{flake}#{attribute}.version", flake = installed_package.original_url, attribute = installed_package.attr_path), true)?;
+      new_packages.push(format!("{name}-{latest_version}"));
+    }
 
-        if cli.flake.is_some() && installed_package.original_url != flake {
-          Operations::throw_if_needed(Err(Error::Unknown { code: 1, message: format!("flake '{flake}' does not provide attribute '{package}'")}));
-        }
+    println!("Packages ({length}) {new_packages_list}\n", length = new_packages.len(), new_packages_list = new_packages.join(" "));
 
-        let evaluate = catch_output(format!("nix eval --raw --offline -- {flake}#{attribute}.version", flake = installed_package.original_url, attribute = installed_package.attr_path), false);
-        if let Err(err) = evaluate {
-          Operations::throw_if_needed(Err(err));
-        }
+    if !cli.noconfirm() && !confirm("Do you want to remove these packages?") {
+      return Err(Error::Unknown { code: 1, message: String::new() });
+    }
 
-        let latest_version = evaluate.unwrap();
-        package_format = format!("{name}-{latest_version}");
+    transaction(cli, nix, HookOperation::Remove, packages, || {
+      nix.profile_remove(packages)?;
 
-        return true;
-      });
+      if cli.clean() {
+        Clean::operate(cli, nix)?;
+      }
 
-      package_format
-    }).collect();
+      Ok(())
+    })
+  }
+}
 
-    println!("Packages ({length}) {new_packages_list}\n", length = new_packages.len(), new_packages_list = new_packages.join(" "));
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
 
-    if !cli.noconfirm && !confirm("Do you want to remove these packages?") {
-      return Err(Error::Unknown { code: 1, message: String::new() });
-    }
+  use super::Remove;
+  use crate::{Cli, Operation, backend::fake::FakeBackend, error::Error};
 
-    let command = cli.prepare_command("nix profile remove");
-    execute_command(format!("{command} -- {}", packages.join(" ")), false)?;
+  #[test]
+  fn removes_installed_element() {
+    let cli = Cli::try_parse_from(["nichts", "-R", "--noconfirm", "hello"]).unwrap();
+    let nix = FakeBackend::new()
+      .with_package("hello", "2.12.1")
+      .with_eval("flake:nixpkgs#legacyPackages.x86_64-linux.hello.version", "2.12.1");
 
-    if cli.clean {
-      Clean::operate(&cli)?;
-    }
+    Remove::operate(&cli, &nix).unwrap();
+
+    assert_eq!(nix.calls().last().unwrap(), &vec!["profile", "remove", "hello"]);
+  }
+
+  #[test]
+  fn refuses_missing_element() {
+    let cli = Cli::try_parse_from(["nichts", "-R", "--noconfirm", "cowsay"]).unwrap();
+    let nix = FakeBackend::new().with_package("hello", "2.12.1");
 
-    Ok(())
+    assert!(matches!(Remove::operate(&cli, &nix), Err(Error::Unknown { .. })));
+    assert_eq!(nix.calls(), vec![vec!["profile", "list"]]);
   }
 }
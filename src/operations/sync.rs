@@ -1,7 +1,6 @@
 use crate::{
-  DEFAULT_FLAKE, Operation, api::query::PackageListing, command::{
-    catch_output, confirm, execute_command
-  }, error::Error, operations::Operations, options::{
+  Operation, api::{query::PackageListing, remote::RemotePackage, store::TransactionSize}, backend::NixBackend, command::confirm,
+  error::Error, hooks::HookOperation, operations::Operations, transaction::transaction, options::{
     Options,
     clean::Clean,
     search::Search,
@@ -11,17 +10,90 @@ use crate::{
 
 pub struct Sync;
 
+fn join_or_none(values: &[String]) -> String {
+  if values.is_empty() {
+    return "None".to_string();
+  }
+
+  values.join("  ")
+}
+
+fn yes_no(value: bool) -> &'static str {
+  if value { "Yes" } else { "No" }
+}
+
+impl Sync {
+  /// Re-evaluates each installable of a failed transaction to name the one that does not evaluate.
+  /// Build failures are left to the error nix already printed.
+  fn find_broken(nix: &dyn NixBackend, installables: &[String]) -> Option<Error> {
+    installables.iter()
+      .find(|installable| nix.eval(&format!("{installable}.drvPath"), false).is_err())
+      .map(|installable| Error::FailedEvaluation { installable: installable.clone() })
+  }
+
+  fn info(cli: &crate::Cli, nix: &dyn NixBackend) -> Result<(), Error> {
+    if cli.packages.is_empty() {
+      return Err(Error::NotSpecified { kind: "target(s)".to_string() });
+    }
+
+    let flake_url = cli.flake_url();
+    let mut packages = Vec::new();
+    for package in &cli.packages {
+      let remote = RemotePackage::new(nix, flake_url, package)
+        .map_err(|_| Error::Unknown { code: 1, message: format!("flake '{flake_url}' does not provide attribute '{package}'") })?;
+      packages.push(remote);
+    }
+
+    if cli.json {
+      if let Ok(serialized) = serde_json::to_string_pretty(&packages) {
+        println!("{serialized}");
+        return Ok(());
+      }
+
+      return Err(Error::FailedJsonSerialization);
+    }
+
+    let print_info = Operations::info_printer(&["Name", "Flake attribute", "Flake Url", "Version", "Description", "Homepage", "Licenses", "Platforms", "Maintainers", "Main Program", "Outputs", "Broken", "Unfree", "Insecure"]);
+
+    for package in packages {
+      let none = "None".to_string();
+
+      print_info("Name", package.name.as_ref().unwrap_or(&package.attr_path));
+      print_info("Flake attribute", &package.attr_path);
+      print_info("Flake Url", &package.flake);
+      print_info("Version", package.version.as_ref().unwrap_or(&none));
+      print_info("Description", package.description.as_ref().unwrap_or(&none));
+      print_info("Homepage", &join_or_none(&package.homepage));
+      print_info("Licenses", &join_or_none(&package.license));
+      print_info("Platforms", &join_or_none(&package.platforms));
+      print_info("Maintainers", &join_or_none(&package.maintainers));
+      print_info("Main Program", package.main_program.as_ref().unwrap_or(&none));
+      print_info("Outputs", &join_or_none(&package.outputs));
+      print_info("Broken", yes_no(package.broken));
+      print_info("Unfree", yes_no(package.unfree));
+      print_info("Insecure", yes_no(package.insecure));
+      println!();
+    }
+
+    Ok(())
+  }
+}
+
 impl Operation for Sync {
-  fn operate(cli: &crate::Cli) -> Result<(), Error> {
-    Options::validate_options(&cli, Operations::Sync)?;
+  fn operate(cli: &crate::Cli, nix: &dyn NixBackend) -> Result<(), Error> {
+    Options::validate_options(cli, Operations::Sync)?;
 
     if cli.upgrade {
-      return Upgrade::operate(&cli);
+      return Upgrade::operate(cli, nix);
     }
 
     let mut packages = cli.packages.clone();
     if cli.search {
-      return Search::operate(&cli);
+      return Search::operate(cli, nix);
+    }
+
+    if cli.info {
+      return Sync::info(cli, nix);
     }
 
     if cli.json {
@@ -31,85 +103,176 @@ impl Operation for Sync {
     let flake_url = cli.flake_url();
 
     if packages.is_empty() {
-      if flake_url.eq(DEFAULT_FLAKE) {
+      if flake_url.eq(cli.default_flake()) {
         return Err(Error::NotSpecified { kind: "target(s)".to_string() });
       }
 
       packages.push("default".to_string());
     }
 
-    let mut installed_packages = PackageListing::new(&cli);
-    let installed_packages_keys: Vec<String> = installed_packages.keys().map(|key| key.clone()).collect();
+    let installed_packages = PackageListing::new(nix)?;
 
     let mut print_seperator_line = false;
-    let new_packages: Vec<String> = packages.iter().map(|package| {
-      let mut package_format = package.clone();
+    let mut new_packages = Vec::new();
+    for package in &packages {
+      let Some(installed_package) = installed_packages.get(package) else {
+        let version = nix.eval(&format!("{flake_url}#{package}.version"), false)
+          .map_err(|_| Error::Unknown { code: 1, message: format!("flake '{flake_url}' does not provide attribute '{package}'") })?;
 
-      if !installed_packages_keys.contains(&package) {
-        let evaluate = catch_output(format!("nix eval --raw -- {flake_url}#{package}.version"), false);
-        if let Ok(version) = evaluate {
-          return format!("{package}-{version}");
-        }
+        new_packages.push(format!("{package}-{version}"));
+        continue;
+      };
 
-        Operations::throw_if_needed(Err(Error::Unknown { code: 1, message: format!("flake '{flake_url}' does not provide attribute '{package}'")}));
+      if cli.flake.is_some() && !installed_package.is_from(flake_url) {
+        return Err(Error::Unknown { code: 1, message: format!("flake '{flake_url}' does not provide attribute '{package}'") });
       }
 
-      let has_installed_packages = installed_packages.any_mut(|name, installed_package| {
-        if !name.eq(package.as_str()) {
-          return false;
-        }
+      let latest_version = nix.eval(&format!("{flake}#{attribute}.version", flake = installed_package.original_url, attribute = installed_package.attr_path), true)?;
 
-        if cli.flake.is_some() && installed_package.original_url != flake_url {
-          Operations::throw_if_needed(Err(Error::Unknown { code: 1, message: format!("flake '{flake_url}' does not provide attribute '{package}'")}));
-        }
+      if installed_package.version.ends_with(&latest_version) && !cli.refresh {
+        Operations::show_warning(format!("{package}-{latest_version} is already up to date"));
+      }
 
-        let evaluate = catch_output(format!("nix eval --raw --offline -- {flake}#{attribute}.version", flake = installed_package.original_url, attribute = installed_package.attr_path), false);
-        if let Err(err) = evaluate {
-          Operations::throw_if_needed(Err(err));
-        }
+      new_packages.push(format!("{flake}#{attribute}-{latest_version}", flake = installed_package.original_url, attribute = installed_package.attr_path));
+      print_seperator_line = true;
+    }
 
-        let latest_version = evaluate.unwrap();
-        package_format = format!("{name}-{latest_version}");
+    if print_seperator_line {
+      println!();
+    }
 
-        if installed_package.version.ends_with(&latest_version) && !cli.refresh {
-          Operations::show_warning(format!("{package_format} is already up to date"));
-        }
+    println!("Packages ({length}) {new_packages_list}\n", length = new_packages.len(), new_packages_list = new_packages.join(" "));
+
+    let installables: Vec<String> = packages.iter().map(|package| format!("{flake_url}#{package}")).collect();
+    let replaced: Vec<String> = packages.iter().filter(|package| installed_packages.get(package).is_some()).cloned().collect();
+
+    match TransactionSize::new(nix, &installed_packages, &replaced, &installables) {
+      Ok(size) => {
+        let print_info = Operations::info_printer(&["Total Download Size", "Total Installed Size", "Net Upgrade Size"]);
+        print_info("Total Download Size", &Operations::format_size(size.download));
+        print_info("Total Installed Size", &Operations::format_size(size.installed));
+        print_info("Net Upgrade Size", &Operations::format_size_delta(size.net));
+        println!();
+      },
+      Err(_) => Operations::show_warning("failed to determine the transaction size"),
+    }
 
-        package_format = format!("{flake}#{attribute}-{latest_version}", flake = installed_package.original_url, attribute = installed_package.attr_path);
+    if !cli.noconfirm() && !confirm("Proceed with installation?") {
+      return Err(Error::Unknown { code: 1, message: String::new() });
+    }
 
-        return true;
-      });
+    transaction(cli, nix, HookOperation::Sync, &packages, || {
+      if let Err(err) = nix.profile_add(&installables) {
+        return Err(Sync::find_broken(nix, &installables).unwrap_or(err));
+      }
 
-      if !print_seperator_line {
-        print_seperator_line = has_installed_packages;
+      if cli.clean() {
+        Clean::operate(cli, nix)?;
       }
 
-      package_format
-    }).collect();
+      Ok(())
+    })
+  }
+}
 
-    if packages.is_empty() {
-      return Err(Error::NotSpecified { kind: "target(s)".to_string() });
-    }
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
 
-    if print_seperator_line {
-      println!("");
-    }
+  use super::Sync;
+  use crate::{Cli, Operation, api::{query::PackageListing, store::TransactionSize}, backend::fake::FakeBackend, error::Error};
 
-    println!("Packages ({length}) {new_packages_list}\n", length = new_packages.len(), new_packages_list = new_packages.join(" "));
+  #[test]
+  fn installs_evaluated_targets() {
+    let cli = Cli::try_parse_from(["nichts", "-S", "--noconfirm", "hello world"]).unwrap();
+    let nix = FakeBackend::new().with_eval("flake:nixpkgs#hello world.version", "2.12.1");
 
-    if !cli.noconfirm && !confirm("Proceed with installation?") {
-      return Err(Error::Unknown { code: 1, message: String::new() });
-    }
+    Sync::operate(&cli, &nix).unwrap();
 
-    let command = cli.prepare_command("nix profile add");
-    for package in packages  {
-      execute_command(format!("{command} -- {flake_url}#{package}"), false)?;
-    }
+    assert_eq!(nix.calls().last().unwrap(), &vec!["profile", "add", "flake:nixpkgs#hello world"]);
+  }
 
-    if cli.clean {
-      Clean::operate(&cli)?;
-    }
+  #[test]
+  fn installs_targets_in_one_transaction() {
+    let cli = Cli::try_parse_from(["nichts", "-S", "--noconfirm", "hello", "cowsay"]).unwrap();
+    let nix = FakeBackend::new()
+      .with_eval("flake:nixpkgs#hello.version", "2.12.1")
+      .with_eval("flake:nixpkgs#cowsay.version", "3.8.4");
 
-    Ok(())
+    Sync::operate(&cli, &nix).unwrap();
+
+    let adds: Vec<Vec<String>> = nix.calls().into_iter().filter(|call| call[..2] == ["profile", "add"]).collect();
+    assert_eq!(adds, vec![vec!["profile", "add", "flake:nixpkgs#hello", "flake:nixpkgs#cowsay"]]);
+  }
+
+  #[test]
+  fn names_broken_installable() {
+    let cli = Cli::try_parse_from(["nichts", "-S", "--noconfirm", "hello", "cowsay"]).unwrap();
+    let nix = FakeBackend::new()
+      .with_eval("flake:nixpkgs#hello.version", "2.12.1")
+      .with_eval("flake:nixpkgs#hello.drvPath", "/nix/store/00000000000000000000000000000000-hello-2.12.1.drv")
+      .with_eval("flake:nixpkgs#cowsay.version", "3.8.4")
+      .failing("add");
+
+    let result = Sync::operate(&cli, &nix);
+    assert!(matches!(result, Err(Error::FailedEvaluation { installable }) if installable == "flake:nixpkgs#cowsay"));
+
+    let nix = nix.with_eval("flake:nixpkgs#cowsay.drvPath", "/nix/store/11111111111111111111111111111111-cowsay-3.8.4.drv");
+    let result = Sync::operate(&cli, &nix);
+    assert!(matches!(result, Err(Error::CommandFailed { code: 1 })));
+  }
+
+  #[test]
+  fn info_reads_remote_metadata() {
+    let cli = Cli::try_parse_from(["nichts", "-Si", "--json", "hello"]).unwrap();
+    let meta = r#"{"name":"hello","version":"2.12.1","outputs":["out"],"description":"A program that produces a familiar, friendly greeting","homepage":["https://www.gnu.org/software/hello/manual/"],"license":["GPL-3.0-or-later"],"platforms":["x86_64-linux"],"maintainers":["stv0g"],"mainProgram":"hello","broken":false,"unfree":false,"insecure":false}"#;
+    let nix = FakeBackend::new().with_eval("flake:nixpkgs#hello", meta);
+
+    Sync::operate(&cli, &nix).unwrap();
+
+    assert_eq!(nix.calls(), vec![vec!["eval", "--json", "flake:nixpkgs#hello"]]);
+  }
+
+  #[test]
+  fn atomic_rolls_back_failed_transaction() {
+    let cli = Cli::try_parse_from(["nichts", "-S", "--noconfirm", "--atomic", "--clean", "hello"]).unwrap();
+    let nix = FakeBackend::new()
+      .with_eval("flake:nixpkgs#hello.version", "2.12.1")
+      .with_history("Version 1 (2025-01-01):\n  flake:nixpkgs#cowsay: ∅ -> 3.8.4\n")
+      .with_history("Version 1 (2025-01-01):\n  flake:nixpkgs#cowsay: ∅ -> 3.8.4\n\nVersion 2 (2025-01-02) <- 1:\n  flake:nixpkgs#hello: ∅ -> 2.12.1\n")
+      .failing("gc");
+
+    assert!(matches!(Sync::operate(&cli, &nix), Err(Error::CommandFailed { code: 1 })));
+    assert_eq!(nix.calls().last().unwrap(), &vec!["profile", "rollback", "1"]);
+  }
+
+  #[test]
+  fn summarizes_transaction_size() {
+    let glibc = "/nix/store/22222222222222222222222222222222-glibc-2.40";
+    let current = format!(r#"{{
+      "/nix/store/00000000000000000000000000000000-hello-2.12.1": {{"narSize": 100, "references": ["{glibc}"]}},
+      "{glibc}": {{"narSize": 1000, "references": []}}
+    }}"#);
+    let incoming = format!(r#"{{
+      "/nix/store/11111111111111111111111111111111-cowsay-3.8.4": {{"narSize": 200, "downloadSize": 50, "references": ["{glibc}"]}},
+      "{glibc}": {{"narSize": 1000, "downloadSize": 400, "references": []}}
+    }}"#);
+    let nix = FakeBackend::new().with_package("hello", "2.12.1").with_path_info(current).with_remote_path_info(incoming).with_present(glibc);
+
+    let listing = PackageListing::new(&nix).unwrap();
+    let size = TransactionSize::new(&nix, &listing, &[], &["flake:nixpkgs#cowsay".to_string()]).unwrap();
+
+    assert_eq!((size.download, size.installed, size.net), (50, 200, 200));
+  }
+
+  #[test]
+  fn rejects_unknown_attribute() {
+    let cli = Cli::try_parse_from(["nichts", "-S", "--noconfirm", "missing"]).unwrap();
+    let nix = FakeBackend::new();
+
+    let result = Sync::operate(&cli, &nix);
+
+    assert!(matches!(result, Err(Error::Unknown { .. })));
+    assert!(!nix.calls().iter().any(|call| call[..2] == ["profile", "add"]));
   }
 }
@@ -1,12 +1,17 @@
-use crate::{CLI_NAME, CLI_VERSION, Operation, command::catch_output, error::Error, operations::Operations, options::Options};
+use crate::{CLI_NAME, CLI_VERSION, Operation, backend::NixBackend, error::Error, operations::Operations, options::Options};
 
 pub struct Version;
 
 impl Operation for Version {
-  fn operate(cli: &crate::Cli) -> Result<(), Error> {
-    Options::validate_options(&cli, Operations::Version)?;
+  fn operate(cli: &crate::Cli, nix: &dyn NixBackend) -> Result<(), Error> {
+    Options::validate_options(cli, Operations::Version)?;
 
-    let output = catch_output("nix --version", true)?;
+    if cli.config_dump {
+      println!("{}", cli.effective_config());
+      return Ok(());
+    }
+
+    let output = nix.version()?;
     print!("{CLI_NAME} {CLI_VERSION} - {output}");
 
     Ok(())
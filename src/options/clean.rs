@@ -1,9 +1,9 @@
-use crate::{ Operation, command::execute_command, error::Error };
+use crate::{ Operation, backend::NixBackend, error::Error };
 
 pub struct Clean;
 
 impl Operation for Clean {
-  fn operate(_cli: &crate::Cli) -> Result<(), Error> {
-    execute_command("nix-collect-garbage --verbose", false)
+  fn operate(_cli: &crate::Cli, nix: &dyn NixBackend) -> Result<(), Error> {
+    nix.collect_garbage()
   }
 }
@@ -2,23 +2,44 @@ use std::{fmt::Display};
 
 use crate::{CLI_NAME, Cli, error::Error, operations::Operations};
 
-#[derive(Clone, PartialEq, PartialOrd, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum Options {
+  Atomic,
+  Bin,
+  Check,
   Clean,
+  Closure,
+  Config,
   Flake,
+  Foreign,
+  Hold,
   Impure,
   Info,
   Json,
+  List,
+  Log,
   NoConfirm,
+  Operation,
+  Owns,
+  Print,
   Profile,
   Quiet,
   Refresh,
   Rollback,
   Search,
+  Since,
+  Unhold,
+  Until,
   Upgrade,
   Wipe,
 }
 
+impl PartialOrd for Options {
+  fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+    Some(self.cmp(other))
+  }
+}
+
 impl Ord for Options {
   fn cmp(&self, other: &Self) -> std::cmp::Ordering {
     self.to_string().cmp(&other.to_string())
@@ -26,30 +47,52 @@ impl Ord for Options {
 }
 
 impl Options {
-  fn all() -> &'static [Options] {
+  pub fn all() -> &'static [Options] {
     &[
+      Options::Atomic,
+      Options::Bin,
+      Options::Check,
       Options::Clean,
+      Options::Closure,
+      Options::Config,
       Options::Flake,
+      Options::Foreign,
+      Options::Hold,
       Options::Impure,
       Options::Info,
       Options::Json,
+      Options::List,
+      Options::Log,
       Options::NoConfirm,
+      Options::Operation,
+      Options::Owns,
+      Options::Print,
       Options::Profile,
       Options::Quiet,
       Options::Refresh,
       Options::Rollback,
       Options::Search,
+      Options::Since,
+      Options::Unhold,
+      Options::Until,
       Options::Upgrade,
       Options::Wipe,
     ]
   }
 
-  fn partial(operation: &Operations) -> &'static [Options] {
+  /// Options accepted without an operation.
+  pub fn global() -> &'static [Options] {
+    &[Options::Clean, Options::Print]
+  }
+
+  pub fn partial(operation: &Operations) -> &'static [Options] {
     match operation {
-      Operations::Query => &[Options::Info, Options::Flake, Options::Json, Options::Profile, Options::Quiet, Options::Search],
-      Operations::Remove => &[Options::Clean, Options::Flake, Options::NoConfirm, Options::Profile, Options::Quiet],
-      Operations::History => &[Options::Clean, Options::Json, Options::NoConfirm, Options::Profile, Options::Quiet, Options::Rollback, Options::Wipe],
-      Operations::Sync => &[Options::Clean, Options::Flake, Options::Impure, Options::Json, Options::NoConfirm, Options::Profile, Options::Quiet, Options::Refresh, Options::Search, Options::Upgrade],
+      Operations::Query => &[Options::Bin, Options::Check, Options::Info, Options::Flake, Options::Foreign, Options::Json, Options::List, Options::Owns, Options::Print, Options::Profile, Options::Quiet, Options::Refresh, Options::Search, Options::Upgrade],
+      Operations::Remove => &[Options::Atomic, Options::Clean, Options::Flake, Options::NoConfirm, Options::Print, Options::Profile, Options::Quiet],
+      Operations::History => &[Options::Clean, Options::Closure, Options::Json, Options::Log, Options::NoConfirm, Options::Operation, Options::Print, Options::Profile, Options::Quiet, Options::Rollback, Options::Since, Options::Until, Options::Wipe],
+      Operations::Sync => &[Options::Atomic, Options::Clean, Options::Flake, Options::Impure, Options::Info, Options::Json, Options::NoConfirm, Options::Print, Options::Profile, Options::Quiet, Options::Refresh, Options::Search, Options::Upgrade],
+      Operations::Database => &[Options::Hold, Options::Print, Options::Unhold],
+      Operations::Version => &[Options::Config, Options::Print],
       _ => &[]
     }
   }
@@ -58,9 +101,21 @@ impl Options {
     match operation {
       Operations::History => &[
         (Options::Json, &[Options::Wipe, Options::Rollback, Options::Clean]),
+        (Options::Log, &[Options::Wipe, Options::Rollback, Options::Clean]),
+        (Options::Closure, &[Options::Wipe, Options::Rollback, Options::Clean, Options::Log]),
+      ],
+      Operations::Database => &[
+        (Options::Hold, &[Options::Unhold]),
+      ],
+      Operations::Sync => &[
+        (Options::Info, &[Options::Atomic, Options::Clean, Options::Search, Options::Upgrade]),
       ],
       Operations::Query => &[
-        (Options::Json, &[Options::Info, Options::Quiet])
+        (Options::Json, &[Options::Info, Options::Quiet]),
+        (Options::Owns, &[Options::Foreign, Options::Info, Options::Search]),
+        (Options::List, &[Options::Info, Options::Owns, Options::Search]),
+        (Options::Upgrade, &[Options::Info, Options::List, Options::Owns, Options::Search]),
+        (Options::Check, &[Options::Info, Options::List, Options::Owns, Options::Upgrade]),
       ],
       _ => &[]
     }
@@ -73,17 +128,32 @@ impl Options {
     }
 
     let set = &[
-      (Options::Clean, cli.clean),
+      (Options::Atomic, cli.atomic),
+      (Options::Bin, cli.bin),
+      (Options::Check, cli.check > 0),
+      (Options::Clean, cli.clean.is_some()),
+      (Options::Closure, cli.closure),
+      (Options::Config, cli.config_dump),
       (Options::Flake, cli.flake.is_some()),
-      (Options::Impure, cli.impure),
+      (Options::Foreign, cli.foreign),
+      (Options::Hold, cli.hold),
+      (Options::Impure, cli.impure.is_some()),
       (Options::Info, cli.info),
       (Options::Json, cli.json),
-      (Options::NoConfirm, cli.noconfirm),
+      (Options::List, cli.list),
+      (Options::Log, cli.log),
+      (Options::NoConfirm, cli.noconfirm.is_some()),
+      (Options::Operation, cli.operation.is_some()),
+      (Options::Owns, cli.owns),
+      (Options::Print, cli.print),
       (Options::Profile, cli.profile.is_some()),
-      (Options::Quiet, cli.quiet),
+      (Options::Quiet, cli.quiet.is_some()),
       (Options::Refresh, cli.refresh),
       (Options::Rollback, cli.rollback),
       (Options::Search, cli.search),
+      (Options::Since, cli.since.is_some()),
+      (Options::Unhold, cli.unhold),
+      (Options::Until, cli.until.is_some()),
       (Options::Upgrade, cli.upgrade),
       (Options::Wipe, cli.wipe.is_some()),
     ];
@@ -93,20 +163,19 @@ impl Options {
 
     for (option, is_set) in set {
       if *is_set {
-        if !allowed_options.contains(&option) {
+        if !allowed_options.contains(option) {
           return Err(Error::InvalidOption {
             option: format!("--{}", option.long()),
             conflicts_with: None
           });
         }
 
-        if let Some((_, conflicts_with)) = confilcts.iter().find(|(opt, _)| opt == option) {
-          if let Some(conflict) = conflicts_with.iter().find(|c| set.iter().any(|(opt, is_set_val)| &opt == c && *is_set_val)) {
-            return Err(Error::InvalidOption {
-              option: format!("--{}", option.long()),
-              conflicts_with: Some(conflict.long().to_string())
-            });
-          }
+        if let Some((_, conflicts_with)) = confilcts.iter().find(|(opt, _)| opt == option)
+          && let Some(conflict) = conflicts_with.iter().find(|c| set.iter().any(|(opt, is_set_val)| &opt == c && *is_set_val)) {
+          return Err(Error::InvalidOption {
+            option: format!("--{}", option.long()),
+            conflicts_with: Some(conflict.long().to_string())
+          });
         }
       }
     }
@@ -130,11 +199,16 @@ impl Options {
 
   pub fn short(&self) -> char {
     match self {
+      Options::Check => 'k',
       Options::Clean => 'c',
       Options::Refresh => 'y',
       Options::Search => 's',
       Options::Upgrade => 'u',
       Options::Info => 'i',
+      Options::List => 'l',
+      Options::Foreign => 'm',
+      Options::Owns => 'o',
+      Options::Print => 'p',
       Options::Quiet => 'q',
       _ => ' '
     }
@@ -143,45 +217,80 @@ impl Options {
   pub fn long(&self) -> &str {
     match self {
       Options::Upgrade => "upgrade",
+      Options::Atomic => "atomic",
+      Options::Bin => "bin",
+      Options::Check => "check",
+      Options::List => "list",
+      Options::Log => "log",
+      Options::Operation => "operation",
+      Options::Since => "since",
+      Options::Until => "until",
       Options::Flake => "flake",
+      Options::Foreign => "foreign",
+      Options::Hold => "hold",
+      Options::Unhold => "unhold",
       Options::Search => "search",
       Options::Info => "info",
       Options::Impure => "impure",
       Options::Json => "json",
       Options::Refresh => "refresh",
       Options::Clean => "clean",
+      Options::Closure => "closure",
+      Options::Config => "config",
       Options::Quiet => "quiet",
       Options::Profile => "profile",
       Options::Wipe => "wipe",
       Options::NoConfirm => "noconfirm",
+      Options::Owns => "owns",
+      Options::Print => "print",
       Options::Rollback => "rollback",
     }
   }
 
   pub fn arguments(&self) -> &str {
     match self {
+      Options::Closure => "<from> [to]",
       Options::Flake => "<path>",
       Options::Profile => "<path>",
+      Options::Owns => "<file>",
+      Options::Operation => "<operation>",
       Options::Search => "<pattern(s)>",
+      Options::Since => "<date>",
+      Options::Until => "<date>",
       Options::Wipe => "[age:<N>d]",
       _ => ""
     }
   }
 
-  fn description(&self) -> &str {
+  pub fn description(&self) -> &str {
     match self {
+      Options::Atomic => "roll back automatically when the transaction fails",
+      Options::Bin => "only list executables added to PATH",
+      Options::Check => "check store paths of installed packages (-kk for closures)",
       Options::Clean => "delete unreachable store objects",
+      Options::Closure => "show the store changes between two generations",
+      Options::Config => "show the effective configuration",
       Options::Flake => "specify a new default flake to install packages from",
+      Options::Foreign => "list packages not installed from the default flake",
+      Options::Hold => "keep packages at their current version during upgrades",
       Options::Impure => "allow access to mutable paths and repositories",
       Options::Info => "display useful metadata",
       Options::Json => "produces output in JSON format",
+      Options::List => "list the files owned by the queried package",
+      Options::Log => "show the transaction log, optionally only for <package(s)>",
       Options::NoConfirm => "do not ask for any confirmation",
+      Options::Operation => "only show log entries of sync, remove, upgrade, rollback or wipe",
+      Options::Owns => "query the package that owns <file>",
+      Options::Print => "print the nix commands instead of running them",
       Options::Profile => "the profile to operate on",
       Options::Quiet => "decrease the logging output",
       Options::Refresh => "consider all previously downloaded files out-of-date",
       Options::Rollback => "roll back to another version",
       Options::Search => "search for packages matching patterns",
-      Options::Upgrade => "upgrade all installed packages",
+      Options::Since => "only show log entries from <date> on",
+      Options::Unhold => "allow held packages to be upgraded again",
+      Options::Until => "only show log entries up to <date>",
+      Options::Upgrade => "upgrade or list outdated installed packages",
       Options::Wipe => "delete non-current versions older than the specified age",
     }
   }
@@ -207,7 +316,7 @@ impl Options {
   fn max_len() -> usize {
     Options::all()
       .iter().map(|option| option.len())
-      .max().unwrap_or_else(|| 0)
+      .max().unwrap_or(0)
   }
 }
 
@@ -1,16 +1,96 @@
-use crate::{Cli, Operation, command::execute_command, error::Error};
+use crate::{Cli, Operation, api::{query::PackageListing, search::SearchResult}, backend::NixBackend, error::Error};
 
 pub struct Search;
 
 impl Operation for Search {
-  fn operate(cli: &Cli) -> Result<(), Error> {
+  fn operate(cli: &Cli, nix: &dyn NixBackend) -> Result<(), Error> {
     if cli.packages.is_empty() {
       return Err(Error::NotSpecified { kind: "pattern(s)".to_string() });
     }
 
     let flake = cli.flake_url();
-    let as_json = cli.json.then(|| "--json").unwrap_or("");
+    let listing = PackageListing::new(nix)?;
+    let results = SearchResult::search(nix, flake, &cli.packages, &listing, cli.refresh)?;
 
-    execute_command(format!("nix search {flake} {as_json} -- {query}", query = &cli.packages.join(" ")), false)
+    if cli.json {
+      if let Ok(serialized) = serde_json::to_string_pretty(&results) {
+        println!("{serialized}");
+        return Ok(());
+      }
+
+      return Err(Error::FailedJsonSerialization);
+    }
+
+    if results.is_empty() {
+      return Err(Error::NoPackageFound);
+    }
+
+    let repository = flake.strip_prefix("flake:").unwrap_or(flake);
+    for result in results {
+      if cli.quiet() {
+        println!("{name}", name = result.name);
+        continue;
+      }
+
+      let installed = match result.installed {
+        Some(version) if version == result.version => " [installed]".to_string(),
+        Some(version) => format!(" [installed: {version}]"),
+        None => String::new(),
+      };
+
+      println!("{repository}/{name} {version}{installed}", name = result.name, version = result.version);
+      if !result.description.is_empty() {
+        println!("    {description}", description = result.description);
+      }
+    }
+
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::{api::{query::PackageListing, search::SearchResult}, backend::fake::FakeBackend};
+
+  #[test]
+  fn marks_installed_results() {
+    let search = r#"{
+      "legacyPackages.x86_64-linux.hello": {"pname": "hello", "version": "2.12.2", "description": "A program that produces a familiar, friendly greeting"},
+      "legacyPackages.x86_64-linux.hello-wayland": {"pname": "hello-wayland", "version": "0-unstable-2024-03-04", "description": "Hello world Wayland client"}
+    }"#;
+    let nix = FakeBackend::new().with_package("hello", "2.12.1").with_search(search);
+
+    let listing = PackageListing::new(&nix).unwrap();
+    let results = SearchResult::search_in(&nix, "flake:nixpkgs", &["hello".to_string()], &listing, None, false).unwrap();
+
+    assert_eq!(results[0].name, "hello");
+    assert_eq!(results[0].installed.as_deref(), Some("2.12.1"));
+    assert_eq!(results[1].installed, None);
+  }
+
+  #[test]
+  fn index_is_reused_until_refreshed() {
+    let cache = std::env::temp_dir().join(format!("nichts-index-{}", std::process::id()));
+    let metadata = r#"{"url":"github:NixOS/nixpkgs/0000000","locked":{"rev":"0000000"}}"#;
+    let packages = r#"[{"attrPath":"hello","pname":"hello","version":"2.12.1","description":"A program that produces a familiar, friendly greeting","mainProgram":"hello"},{"attrPath":"cowsay","pname":"cowsay","version":"3.8.4","description":"A program which generates ASCII pictures of a cow","mainProgram":"cowsay"}]"#;
+    let nix = FakeBackend::new()
+      .with_package("hello", "2.12.1")
+      .with_flake_metadata(metadata)
+      .with_eval("github:NixOS/nixpkgs/0000000#legacyPackages.x86_64-linux", packages);
+
+    let listing = PackageListing::new(&nix).unwrap();
+    let first = SearchResult::search_in(&nix, "flake:nixpkgs", &["^hel+o$".to_string()], &listing, Some(&cache), false).unwrap();
+    let second = SearchResult::search_in(&nix, "flake:nixpkgs", &["COW".to_string()], &listing, Some(&cache), false).unwrap();
+    let metadata_calls = nix.calls().iter().filter(|call| call[..2] == ["flake", "metadata"]).count();
+    let refreshed = SearchResult::search_in(&nix, "flake:nixpkgs", &["hello".to_string()], &listing, Some(&cache), true).unwrap();
+    std::fs::remove_dir_all(&cache).unwrap();
+
+    assert_eq!(first.len(), 1);
+    assert_eq!(first[0].installed.as_deref(), Some("2.12.1"));
+    assert_eq!(second[0].main_program.as_deref(), Some("cowsay"));
+    assert_eq!(refreshed.len(), 1);
+    assert_eq!(metadata_calls, 1);
+    assert_eq!(nix.calls().iter().filter(|call| call[..2] == ["flake", "metadata"]).count(), 2);
+    assert_eq!(nix.calls().iter().filter(|call| call[..2] == ["eval", "--json"]).count(), 1);
   }
 }
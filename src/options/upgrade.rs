@@ -1,22 +1,167 @@
-use crate::{Operation, command::execute_command, error::Error, options::clean::Clean};
+use crate::{
+  Operation, api::{history::UNSET_VERSION, hold::HoldList, query::PackageListing, upgrades::PendingUpgrade}, backend::NixBackend, command::{confirm, prompt},
+  error::Error, hooks::HookOperation, operations::Operations, options::clean::Clean, transaction::transaction
+};
 
 pub struct Upgrade;
 
+impl Upgrade {
+  /// Asks for elements to leave out of the upgrade, re-asking until every name is part of the plan.
+  fn exclude(upgrades: &mut Vec<PendingUpgrade>, mut ask: impl FnMut() -> String) {
+    loop {
+      let answer = ask();
+      let excluded: Vec<&str> = answer.split_whitespace().collect();
+
+      if let Some(unknown) = excluded.iter().find(|name| !upgrades.iter().any(|upgrade| upgrade.name == **name)) {
+        Operations::show_warning(format!("'{unknown}' is not part of this upgrade"));
+        continue;
+      }
+
+      upgrades.retain(|upgrade| !excluded.contains(&upgrade.name.as_str()));
+      return;
+    }
+  }
+}
+
 impl Operation for Upgrade {
-  fn operate(cli: &crate::Cli) -> Result<(), Error> {
-    let packages = cli.packages.join(" ");
-    let command = cli.prepare_command("nix profile upgrade");
+  fn operate(cli: &crate::Cli, nix: &dyn NixBackend) -> Result<(), Error> {
+    let mut listing = PackageListing::new(nix)?;
+
+    if let Some(missing) = cli.packages.iter().find(|package| listing.get(package).is_none()) {
+      return Err(Error::Unknown { code: 1, message: format!("target not found: {missing}") });
+    }
+
+    if !cli.packages.is_empty() {
+      listing.retain(|name, _| cli.packages.iter().any(|package| package == name));
+    }
+
+    let mut upgrades = PendingUpgrade::collect(nix, &listing, &HoldList::new(cli))?;
+
+    if cli.json {
+      if let Ok(serialized) = serde_json::to_string_pretty(&upgrades) {
+        println!("{serialized}");
+        return Ok(());
+      }
 
-    if packages.is_empty() {
-      execute_command(format!("{command} --all"), false)?;
-    } else {
-      execute_command(format!("{command} -- {packages}"), false)?;
+      return Err(Error::FailedJsonSerialization);
     }
 
-    if cli.clean {
-      Clean::operate(&cli)?;
+    let version = |version: &Option<String>| version.clone().unwrap_or(UNSET_VERSION.to_string());
+
+    for upgrade in upgrades.iter().filter(|upgrade| upgrade.held) {
+      Operations::show_warning(format!("{name}: ignoring package upgrade ({installed} -> {available})",
+        name = upgrade.name, installed = version(&upgrade.installed_version), available = version(&upgrade.available_version)
+      ));
+    }
+    upgrades.retain(|upgrade| !upgrade.held);
+
+    if upgrades.is_empty() {
+      println!(" there is nothing to do");
+      return Ok(());
+    }
+
+    println!("Packages ({length})", length = upgrades.len());
+    for upgrade in &upgrades {
+      let unknown = if upgrade.evaluated { "" } else { " (failed to evaluate)" };
+      println!("  {name} {installed} -> {available}{unknown}", name = upgrade.name, installed = version(&upgrade.installed_version), available = version(&upgrade.available_version));
+    }
+    println!();
+
+    if !cli.noconfirm() {
+      Upgrade::exclude(&mut upgrades, || prompt("Packages to exclude (e.g. 'hello cowsay'), leave empty to continue:"));
+
+      if upgrades.is_empty() {
+        println!(" there is nothing to do");
+        return Ok(());
+      }
+
+      if !confirm("Proceed with upgrade?") {
+        return Err(Error::Unknown { code: 1, message: String::new() });
+      }
     }
 
-    Ok(())
+    let elements: Vec<String> = upgrades.into_iter().map(|upgrade| upgrade.name).collect();
+    transaction(cli, nix, HookOperation::Upgrade, &elements, || {
+      nix.profile_upgrade(&elements)?;
+
+      if cli.clean() {
+        Clean::operate(cli, nix)?;
+      }
+
+      Ok(())
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use super::Upgrade;
+  use crate::{Cli, Operation, api::{hold::HoldList, query::PackageListing, upgrades::PendingUpgrade}, backend::fake::FakeBackend};
+
+  fn backend() -> FakeBackend {
+    FakeBackend::new()
+      .with_package("hello", "2.12.1")
+      .with_package("cowsay", "3.8.4")
+      .with_eval("flake:nixpkgs#legacyPackages.x86_64-linux.cowsay", r#"{"version":"3.8.4","outPaths":["/nix/store/11111111111111111111111111111111-cowsay-3.8.4"]}"#)
+      .with_eval("flake:nixpkgs#legacyPackages.x86_64-linux.hello", r#"{"version":"2.12.2","outPaths":["/nix/store/22222222222222222222222222222222-hello-2.12.2"]}"#)
+  }
+
+  #[test]
+  fn upgrades_only_changed_elements() {
+    let cli = Cli::try_parse_from(["nichts", "-Su", "--noconfirm"]).unwrap();
+    let nix = backend();
+
+    Upgrade::operate(&cli, &nix).unwrap();
+
+    assert_eq!(nix.calls().last().unwrap(), &vec!["profile", "upgrade", "hello"]);
+  }
+
+  #[test]
+  fn upgrades_rebuilds_and_unevaluated_elements() {
+    let cli = Cli::try_parse_from(["nichts", "-Su", "--noconfirm"]).unwrap();
+    let nix = FakeBackend::new()
+      .with_package("hello", "2.12.1")
+      .with_package("cowsay", "3.8.4")
+      .with_eval("flake:nixpkgs#legacyPackages.x86_64-linux.cowsay", r#"{"version":"3.8.4","outPaths":["/nix/store/33333333333333333333333333333333-cowsay-3.8.4"]}"#);
+
+    Upgrade::operate(&cli, &nix).unwrap();
+
+    assert_eq!(nix.calls().last().unwrap(), &vec!["profile", "upgrade", "cowsay", "hello"]);
+  }
+
+  #[test]
+  fn skips_held_elements() {
+    let mut cli = Cli::try_parse_from(["nichts", "-Su", "--noconfirm"]).unwrap();
+    cli.config.hold = Some(vec!["hello".to_string()]);
+    let nix = backend();
+
+    Upgrade::operate(&cli, &nix).unwrap();
+
+    assert!(!nix.calls().iter().any(|call| call[..2] == ["profile", "upgrade"]));
+  }
+
+  #[test]
+  fn excludes_answered_elements() {
+    let nix = FakeBackend::new().with_package("hello", "2.12.1").with_package("cowsay", "3.8.4");
+    let listing = PackageListing::new(&nix).unwrap();
+    let mut upgrades = PendingUpgrade::collect(&nix, &listing, &HoldList::default()).unwrap();
+
+    let mut answers = vec!["hello", "firefox"];
+    Upgrade::exclude(&mut upgrades, || answers.pop().unwrap().to_string());
+
+    assert!(answers.is_empty());
+    assert_eq!(upgrades.iter().map(|upgrade| upgrade.name.as_str()).collect::<Vec<_>>(), ["cowsay"]);
+  }
+
+  #[test]
+  fn json_plan_does_not_upgrade() {
+    let cli = Cli::try_parse_from(["nichts", "-Su", "--json"]).unwrap();
+    let nix = backend();
+
+    Upgrade::operate(&cli, &nix).unwrap();
+
+    assert!(!nix.calls().iter().any(|call| call[..2] == ["profile", "upgrade"]));
   }
 }
@@ -0,0 +1,121 @@
+use std::{process::exit, sync::{Once, atomic::{AtomicBool, Ordering}}};
+
+use crate::{
+  Cli, api::{history::HistoryVersions, log::{LogEntry, LogSnapshot, TransactionLog}}, backend::NixBackend, command::confirm,
+  error::Error, hooks::{HookOperation, HookWhen, Hooks}, operations::Operations
+};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static CATCHING: AtomicBool = AtomicBool::new(false);
+static HANDLER: Once = Once::new();
+
+/// Lets nix receive Ctrl-C on its own while `body` runs, so nichts stays alive to clean up after it.
+/// Anywhere else, e.g. at the rollback prompt, Ctrl-C exits as usual.
+fn catch_interrupts<T>(body: impl FnOnce() -> T) -> T {
+  HANDLER.call_once(|| {
+    let handler = || {
+      if !CATCHING.load(Ordering::SeqCst) {
+        exit(130);
+      }
+
+      INTERRUPTED.store(true, Ordering::SeqCst);
+    };
+
+    if let Err(err) = ctrlc::set_handler(handler) {
+      Operations::show_warning(format!("failed to install interrupt handler: {err}"));
+    }
+  });
+
+  INTERRUPTED.store(false, Ordering::SeqCst);
+  CATCHING.store(true, Ordering::SeqCst);
+  let result = body();
+  CATCHING.store(false, Ordering::SeqCst);
+
+  result
+}
+
+fn current_generation(nix: &dyn NixBackend) -> Option<usize> {
+  HistoryVersions::new(nix).ok().and_then(|versions| versions.current())
+}
+
+/// Runs `body` between the hooks for `operation` and records it in the transaction log.
+pub fn logged<F: FnOnce() -> Result<(), Error>>(cli: &Cli, nix: &dyn NixBackend, operation: HookOperation, packages: &[String], body: F) -> Result<(), Error> {
+  let hooks = Hooks::load(cli);
+  hooks.run(cli, HookWhen::Pre, operation, packages)?;
+
+  if cli.print || TransactionLog::path(cli).is_none() {
+    body()?;
+    return hooks.run(cli, HookWhen::Post, operation, packages);
+  }
+
+  let before = LogSnapshot::take(nix);
+  let result = body();
+
+  let flags = std::env::args().skip(1).filter(|arg| arg.starts_with('-')).collect();
+  let status = result.as_ref().err().map(Error::exit_code).unwrap_or(0);
+  if let Err(err) = TransactionLog::append(cli, &LogEntry::new(operation, flags, &before, &LogSnapshot::take(nix), status)) {
+    Operations::show_warning(err.to_string());
+  }
+
+  result?;
+  hooks.run(cli, HookWhen::Post, operation, packages)
+}
+
+/// Runs `body` as one profile transaction, offering to roll back to the generation
+/// it started from when it fails or gets interrupted (automatically with `--atomic`).
+/// Hooks for `operation` run around it with the affected `packages`.
+pub fn transaction<F: FnOnce() -> Result<(), Error>>(cli: &Cli, nix: &dyn NixBackend, operation: HookOperation, packages: &[String], body: F) -> Result<(), Error> {
+  logged(cli, nix, operation, packages, || {
+    let generation = current_generation(nix);
+    let result = catch_interrupts(body);
+
+    let interrupted = INTERRUPTED.load(Ordering::SeqCst);
+    if result.is_ok() && !interrupted {
+      return result;
+    }
+
+    let result = result.and(Err(Error::Interrupted));
+    let Some(generation) = generation else {
+      return result;
+    };
+
+    if current_generation(nix) == Some(generation) {
+      return result;
+    }
+
+    let reason = if interrupted { "interrupted" } else { "failed" };
+    let rollback = cli.atomic || (!cli.noconfirm() && confirm(format!("Transaction {reason}, roll back to generation {generation}?")));
+
+    if rollback {
+      nix.profile_rollback(generation).map_err(|_| Error::FailedRollback)?;
+      println!("restored generation {generation}");
+    }
+
+    result
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use super::transaction;
+  use crate::{Cli, api::log::TransactionLog, backend::{NixBackend, fake::FakeBackend}, hooks::HookOperation};
+
+  #[test]
+  fn appends_each_transaction_to_the_log() {
+    let dir = std::env::temp_dir().join(format!("nichts-log-{}", std::process::id()));
+    let mut cli = Cli::try_parse_from(["nichts", "-S", "--noconfirm", "hello"]).unwrap();
+    cli.config.state_dir = Some(dir.clone());
+    let nix = FakeBackend::new().with_package("hello", "2.12.1");
+    let failing = FakeBackend::new().with_package("hello", "2.12.1").failing("add");
+
+    let installables = ["nixpkgs#hello".to_string()];
+    transaction(&cli, &nix, HookOperation::Sync, &cli.packages, || nix.profile_add(&installables)).unwrap();
+    transaction(&cli, &failing, HookOperation::Sync, &cli.packages, || failing.profile_add(&installables)).unwrap_err();
+    let entries = TransactionLog::read(&cli).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(entries.iter().map(|entry| (entry.operation, entry.status)).collect::<Vec<_>>(), [(HookOperation::Sync, 0), (HookOperation::Sync, 1)]);
+  }
+}